@@ -9,6 +9,8 @@ pub struct StreamRequest {
     pub cwd: String,
     pub command: Vec<String>,
     #[serde(default)]
+    pub env: Vec<(String, String)>,
+    #[serde(default)]
     pub local: bool,
     #[serde(default)]
     pub single: bool,
@@ -19,6 +21,9 @@ pub struct StreamResult {
     pub id: u64,
     pub exit_code: i32,
     pub stdout: String,
+    /// wall time of the command in seconds
+    #[serde(default)]
+    pub duration: f32,
 }
 
 pub async fn xge(open_monitor: bool) -> (tokio::process::Child, std::io::Result<TcpStream>) {
@@ -14,11 +14,14 @@ fn accept_commands(stream: TcpStream) {
         if let Ok(request) = request {
             let mut args = request.command.iter();
             let mut cmd = std::process::Command::new(args.next().unwrap());
+            cmd.envs(request.env.iter().cloned());
             for arg in args {
                 cmd.arg(arg);
             }
 
+            let start = std::time::Instant::now();
             let maybe_output = cmd.output();
+            let duration = start.elapsed().as_secs_f32();
             match maybe_output {
                 Ok(output) => {
                     let exit_code = output.status.code().unwrap_or(-7787);
@@ -28,13 +31,14 @@ fn accept_commands(stream: TcpStream) {
                         str::from_utf8(&output.stderr).unwrap_or("couldn't decode output!");
                     let output_str = stderr.to_owned() + stdout;
 
-                    report(request.id, exit_code, &output_str);
+                    report(request.id, exit_code, &output_str, duration);
                 }
                 Err(e) => {
                     report(
                         request.id,
                         -7787,
                         &format!("XGE-Launcher: failed to execute process: {}", e),
+                        duration,
                     );
                 }
             }
@@ -43,11 +47,12 @@ fn accept_commands(stream: TcpStream) {
     println!("mwt done");
 }
 
-fn report(id: u64, exit_code: i32, output: &str) {
+fn report(id: u64, exit_code: i32, output: &str, duration: f32) {
     let result = xge_lib::StreamResult {
         id,
         exit_code,
         stdout: output.to_string(),
+        duration,
     };
     println!("mwt {}", serde_json::to_string(&result).unwrap());
 }
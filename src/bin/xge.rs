@@ -17,6 +17,7 @@ fn accept_commands(stream: TcpStream) {
 
             let mut cmd = Command::new("xgSubmit");
             cmd.current_dir(request.cwd)
+                .envs(request.env)
                 .arg(format!("/caption={}", request.title.replace(' ', "_")));
             if request.local {
                 cmd.arg("/allowremote=off");
@@ -24,6 +25,8 @@ fn accept_commands(stream: TcpStream) {
             if request.single {
                 cmd.arg("/wait"); // this waits for all tasks, we would actually only have to wait for tasks of the same app
             }
+            // xgSubmit only hands the task over to XGE, results are reported via the socket
+            #[allow(clippy::zombie_processes)]
             cmd.arg("/command")
                 .arg(this_exe)
                 .arg("w")
@@ -36,23 +39,26 @@ fn accept_commands(stream: TcpStream) {
     println!("mwt done");
 }
 
-fn report(id: u64, exit_code: i32, output: &str) {
+fn report(id: u64, exit_code: i32, output: &str, duration: f32) {
     let result = xge_lib::StreamResult {
         id,
         exit_code,
         stdout: output.to_string(),
+        duration,
     };
     println!("mwt {}", serde_json::to_string(&result).unwrap());
     ::std::process::exit(exit_code);
 }
 
 fn execute_wrapped(id: u64, exe: &str, args: Vec<&String>) {
-    let mut cmd = Command::new(&exe);
+    let mut cmd = Command::new(exe);
     for arg in args {
         cmd.arg(arg);
     }
 
+    let start = std::time::Instant::now();
     let maybe_output = cmd.output();
+    let duration = start.elapsed().as_secs_f32();
     match maybe_output {
         Ok(output) => {
             let exit_code = output.status.code().unwrap_or(-7787);
@@ -60,13 +66,14 @@ fn execute_wrapped(id: u64, exe: &str, args: Vec<&String>) {
             let stderr = String::from_utf8_lossy(&output.stderr);
             let output_str = stderr + stdout;
 
-            report(id, exit_code, &output_str);
+            report(id, exit_code, &output_str, duration);
         }
         Err(e) => {
             report(
                 id,
                 -7787,
                 &format!("XGE-Launcher: failed to execute process: {}", e),
+                duration,
             );
         }
     }
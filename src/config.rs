@@ -1,9 +1,11 @@
+use crate::discovery::{GtestDiscovery, GtestRequest};
+use crate::selection::AppSelector;
 use serde::Deserialize;
-use simple_eyre::eyre::{eyre, ContextCompat, Result};
-use std::collections::HashMap;
+use simple_eyre::eyre::{eyre, ContextCompat, Result, WrapErr};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
-use std::io::BufRead;
 use std::iter::FromIterator;
+use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
 
 // The "*Config" structs in this module have exactly the same structure as apps.json.
@@ -25,6 +27,9 @@ pub struct AppConfig {
     pub tags: Vec<String>,
     #[serde(default = "default_retcodes")]
     pub accepted_returncodes: Vec<i32>,
+    /// exit codes that are reported as something else than pass or fail, like {"77": "skipped"}
+    #[serde(default)]
+    pub returncode_outcomes: HashMap<i32, Outcome>,
     #[serde(default)]
     pub disabled: bool,
     pub builds: HashMap<String, BuildConfig>,
@@ -35,7 +40,12 @@ pub struct AppConfig {
     pub checkout_parent: bool,
     #[serde(default = "default_true")]
     pub supports_gtest_batching: bool,
-    pub env_path: Option<String>, // TODO
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// prepended to PATH (and LD_LIBRARY_PATH on Linux)
+    pub env_path: Option<String>,
+    /// how many tests of this app may run at the same time in local runs
+    pub max_parallel: Option<NonZeroUsize>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -51,7 +61,15 @@ pub struct BuildConfig {
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct TestPresetConfig {
+    /// presets whose groups are run as well
+    #[serde(default)]
+    pub extends: Vec<String>,
     pub command: Option<CommandTemplate>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub accepted_returncodes: Option<Vec<i32>>,
+    #[serde(default)]
+    pub returncode_outcomes: HashMap<i32, Outcome>,
     pub id_pattern: Option<String>,
     pub groups: Vec<TestGroupConfig>,
 }
@@ -59,18 +77,70 @@ pub struct TestPresetConfig {
 #[derive(Debug, Deserialize, Clone)]
 pub struct CommandTemplate(pub Vec<String>);
 
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    Skipped,
+    KnownIssue,
+}
+impl Outcome {
+    /// Codes in `outcomes` take precedence, other codes pass if they are accepted.
+    pub fn from_exit_code(
+        exit_code: i32,
+        accepted_returncodes: &[i32],
+        outcomes: &HashMap<i32, Outcome>,
+    ) -> Outcome {
+        match outcomes.get(&exit_code) {
+            Some(outcome) => *outcome,
+            None if accepted_returncodes.contains(&exit_code) => Outcome::Pass,
+            None => Outcome::Fail,
+        }
+    }
+
+    /// Only failures fail the run (and are retried).
+    pub fn is_success(self) -> bool {
+        self != Outcome::Fail
+    }
+}
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "pass"),
+            Outcome::Fail => write!(f, "fail"),
+            Outcome::Skipped => write!(f, "skipped"),
+            Outcome::KnownIssue => write!(f, "known-issue"),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct TestGroupConfig {
     pub command: Option<CommandTemplate>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
     pub find_glob: Option<String>,
+    pub find_parent_dir: Option<String>,
     pub find_gtest: Option<String>,
+    pub input_strings: Option<Vec<String>>,
     pub timeout: Option<f32>,
     pub timeout_if_changed: Option<f32>,
+    pub accepted_returncodes: Option<Vec<i32>>,
+    #[serde(default)]
+    pub returncode_outcomes: HashMap<i32, Outcome>,
     #[serde(default)]
     pub testcases_dependencies: Vec<String>,
     #[serde(default = "value_xge")]
     pub execution_style: String,
     pub exclusion_list: Option<String>,
+    /// prepended to the command, like ["valgrind", "--error-exitcode=99", "--"]
+    pub wrapper: Option<CommandTemplate>,
+    /// multiplies the timeout while the wrapper is used
+    pub wrapper_timeout_factor: Option<f32>,
+    /// named resources that each test holds while it runs, like {"license": 1}
+    #[serde(default)]
+    pub resources: HashMap<String, usize>,
 }
 
 #[derive(Debug)]
@@ -84,6 +154,7 @@ pub struct App {
     pub globber_matches_parent: bool,
     pub checkout_parent: bool,
     pub supports_gtest_batching: bool,
+    pub max_parallel: Option<NonZeroUsize>,
 }
 
 #[derive(Debug, Clone)]
@@ -104,69 +175,189 @@ pub struct TestPreset {
 #[derive(Debug, Clone)]
 pub struct TestGroup {
     pub command: CommandTemplate,
+    pub env: HashMap<String, String>,
     pub find_glob: Option<String>,
+    pub find_parent_dir: Option<String>,
     pub find_gtest: Option<String>,
+    pub input_strings: Option<Vec<String>>,
     pub timeout: Option<f32>,
     pub timeout_if_changed: Option<f32>,
     pub accepted_returncodes: Vec<i32>,
+    pub returncode_outcomes: HashMap<i32, Outcome>,
     pub testcases_dependencies: Vec<String>,
     pub execution_style: String,
     pub exclusion_list: Option<String>,
+    pub wrapper: Option<CommandTemplate>,
+    pub wrapper_timeout_factor: Option<f32>,
+    pub resources: HashMap<String, usize>,
 }
 
 impl AppsConfig {
-    pub fn load(dev_dir: &Option<PathBuf>, build_dir: &Path) -> Result<Self> {
-        let apps_json_path = match (dev_dir, build_dir) {
-            (Some(dev_dir), _) if dev_dir.join("tools/mwtest/apps.json").exists() => {
-                dev_dir.join("tools/mwtest/apps.json")
+    /// Returns the directory that contains apps.json and/or the fragments in apps.d.
+    pub fn find(dev_dir: &Option<PathBuf>, build_dir: &Path) -> Result<PathBuf> {
+        let candidates = dev_dir
+            .iter()
+            .map(|d| d.join("tools/mwtest"))
+            .chain(std::iter::once(build_dir.join("mwtest")));
+        for dir in candidates {
+            if !AppsConfig::files(&dir).is_empty() {
+                return Ok(dir);
             }
-            (_, build_dir) if build_dir.join("mwtest/apps.json").exists() => {
-                build_dir.join("mwtest/apps.json")
+        }
+        Err(eyre!("Could not find apps.json!"))
+    }
+
+    /// apps.json (if it exists), followed by all apps.d/*.json fragments.
+    pub fn files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![];
+        if dir.join("apps.json").is_file() {
+            files.push(dir.join("apps.json"));
+        }
+        let fragments_glob = dir.join("apps.d").join("*.json");
+        if let Ok(fragments) = glob::glob(fragments_glob.to_str().unwrap()) {
+            // glob yields paths in alphabetical order
+            files.extend(fragments.filter_map(|f| f.ok()));
+        }
+        files
+    }
+
+    pub fn load(dev_dir: &Option<PathBuf>, build_dir: &Path) -> Result<Self> {
+        AppsConfig::load_dir(&AppsConfig::find(dev_dir, build_dir)?)
+    }
+
+    pub fn load_dir(dir: &Path) -> Result<Self> {
+        let fragments = AppsConfig::files(dir)
+            .into_iter()
+            .map(|f| Ok((AppsConfig::load_file(&f)?, f)))
+            .collect::<Result<Vec<_>>>()?;
+        AppsConfig::merge(fragments)
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self> {
+        let reader = std::io::BufReader::new(File::open(path)?);
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        // report the JSON path of the offending value, not only line and column
+        serde_path_to_error::deserialize(&mut deserializer)
+            .map_err(|e| eyre!("{}: {}", path.display(), e))
+    }
+
+    /// Merges apps.json fragments. App names, aliases and tags must not be shared between files.
+    pub fn merge(fragments: Vec<(AppsConfig, PathBuf)>) -> Result<Self> {
+        if let Some((name, first, second)) = AppsConfig::find_duplicates(&fragments).first() {
+            return Err(eyre!(
+                "'{}' is defined in both {} and {}",
+                name,
+                first.display(),
+                second.display()
+            ));
+        }
+        let apps = fragments.into_iter().flat_map(|(f, _)| f.0).collect();
+        Ok(AppsConfig(apps))
+    }
+
+    /// Returns all names that are used in more than one fragment, with the files that define them.
+    pub fn find_duplicates(fragments: &[(AppsConfig, PathBuf)]) -> Vec<(String, PathBuf, PathBuf)> {
+        let mut owners: HashMap<String, &PathBuf> = HashMap::new();
+        let mut duplicates = vec![];
+        for (fragment, path) in fragments {
+            let mut names: Vec<String> = fragment
+                .0
+                .iter()
+                .flat_map(|(name, app)| {
+                    std::iter::once(name)
+                        .chain(app.alias.iter())
+                        .chain(app.tags.iter())
+                })
+                .map(|n| n.to_lowercase())
+                .collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                match owners.get(&name) {
+                    Some(owner) => duplicates.push((name, (*owner).clone(), path.clone())),
+                    None => {
+                        owners.insert(name, path);
+                    }
+                }
             }
-            _ => return Err(eyre!("Could not find apps.json!")),
-        };
-        Ok(serde_json::from_reader(File::open(apps_json_path)?)?)
+        }
+        duplicates
     }
 
-    pub fn app_names(self: &Self) -> Vec<String> {
-        let mut names: Vec<_> = self.0.iter().map(|(n, _)| n.to_string()).collect();
+    pub fn app_names(&self) -> Vec<String> {
+        let mut names: Vec<_> = self.0.keys().map(|n| n.to_string()).collect();
         names.sort();
         names
     }
 
     pub fn select_build_and_preset(
-        self: Self,
+        self,
         app_names: &[String],
         input_paths: &InputPaths,
     ) -> Result<Apps> {
-        let selected_app_configs = self.0.into_iter().filter(|(name, config)| {
-            app_names.iter().any(|n| {
-                let n = n.to_lowercase();
-                n == name.to_lowercase()
-                    || n == "all"
-                    || config.alias.iter().any(|a| a.to_lowercase() == n)
-                    || config.tags.iter().any(|t| t.to_lowercase() == n)
-            })
-        });
+        self.select(app_names, input_paths, true)
+    }
+
+    /// Like `select_build_and_preset`, for commands that don't run tests (like "build" and
+    /// "info"), so the preset doesn't have to be defined for the apps.
+    pub fn select_build(self, app_names: &[String], input_paths: &InputPaths) -> Result<Apps> {
+        self.select(app_names, input_paths, false)
+    }
+
+    fn select(
+        self,
+        app_names: &[String],
+        input_paths: &InputPaths,
+        check_presets: bool,
+    ) -> Result<Apps> {
+        let selector = match AppSelector::parse_all(app_names)? {
+            Some(selector) => selector,
+            None => return Ok(Apps(HashMap::new())),
+        };
+        let selected_app_configs: Vec<_> = self
+            .0
+            .into_iter()
+            .filter(|(name, config)| selector.matches(name, config))
+            .collect();
+
+        // a typo in a preset name would otherwise silently select no tests; the default preset
+        // doesn't have to exist, some apps have no "ci" tests
+        let presets = PresetSelection::parse(&input_paths.preset)?;
+        let preset_given = input_paths.sources.preset != ValueSource::Default;
+        if check_presets && preset_given && !selected_app_configs.is_empty() {
+            for preset in presets.include.iter().chain(presets.exclude.iter()) {
+                if !selected_app_configs
+                    .iter()
+                    .any(|(_, config)| config.tests.contains_key(preset))
+                {
+                    return Err(eyre!(
+                        "preset '{}' is not defined for any of the selected apps",
+                        preset
+                    ));
+                }
+            }
+        }
 
         let apps: Result<Vec<_>> = selected_app_configs
+            .into_iter()
             .filter_map(|(name, config)| {
                 config
-                    .select_build_and_preset(&name, input_paths)
+                    .select_build_and_preset(&name, input_paths, &presets)
                     .map(|option_app| option_app.map(|app| (name, app)))
                     .transpose()
             })
             .collect();
-        let apps = HashMap::from_iter(apps?.into_iter());
+        let apps = HashMap::from_iter(apps?);
         Ok(Apps(apps))
     }
 }
 
 impl AppConfig {
     fn select_build_and_preset(
-        mut self: Self,
+        mut self,
         name: &str,
         input_paths: &InputPaths,
+        presets: &PresetSelection,
     ) -> Result<Option<App>> {
         let build_type: &str = match &input_paths.build_type {
             Some(b) => b,
@@ -183,15 +374,98 @@ impl AppConfig {
             return Ok(None);
         }
 
-        let build = Build::from(&build_config, &input_paths);
-        let tests = input_paths
-            .preset
-            .split('+')
-            .filter_map(|p| self.tests.get(p))
-            .cloned()
-            .collect();
+        let build = Build::from(&build_config, input_paths);
+        let included = self
+            .resolve_presets(&presets.include)
+            .wrap_err_with(|| name.to_string())?;
+        let excluded = self
+            .resolve_presets(&presets.exclude)
+            .wrap_err_with(|| name.to_string())?;
+        let mut tests = vec![];
+        for preset in included {
+            if !excluded.contains(&preset) && !tests.contains(&preset) {
+                tests.push(preset);
+            }
+        }
+        let tests = tests.iter().map(|p| self.tests[p].clone()).collect();
         Ok(Some(App::from(self, input_paths, build, tests)))
     }
+
+    /// Resolves all given presets that are defined for this app; other apps may define the rest.
+    fn resolve_presets(&self, presets: &[String]) -> Result<Vec<String>> {
+        let mut resolved = vec![];
+        for preset in presets.iter().filter(|p| self.tests.contains_key(*p)) {
+            resolved.extend(self.resolve_preset(preset)?);
+        }
+        Ok(resolved)
+    }
+
+    /// Returns the given preset, followed by all presets it extends (directly or indirectly).
+    pub fn resolve_preset(&self, preset: &str) -> Result<Vec<String>> {
+        let mut resolved = vec![];
+        self.resolve_preset_into(preset, &mut resolved, &mut vec![])?;
+        Ok(resolved)
+    }
+
+    fn resolve_preset_into(
+        &self,
+        preset: &str,
+        resolved: &mut Vec<String>,
+        stack: &mut Vec<String>,
+    ) -> Result<()> {
+        if stack.iter().any(|p| p == preset) {
+            return Err(eyre!(
+                "preset '{}' extends itself ({} -> {})",
+                preset,
+                stack.join(" -> "),
+                preset
+            ));
+        }
+        let config = self
+            .tests
+            .get(preset)
+            .wrap_err_with(|| format!("preset '{}' is not defined", preset))?;
+        if !resolved.iter().any(|p| p == preset) {
+            resolved.push(preset.to_string());
+        }
+        stack.push(preset.to_string());
+        for parent in &config.extends {
+            self.resolve_preset_into(parent, resolved, stack)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+}
+
+/// Parsed --preset value: presets are combined with '+' and removed with '-' (like "nightly-ci").
+struct PresetSelection {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+impl PresetSelection {
+    fn parse(selection: &str) -> Result<PresetSelection> {
+        let mut presets = PresetSelection {
+            include: vec![],
+            exclude: vec![],
+        };
+        let mut is_excluded = false;
+        for token in selection.split_inclusive(&['+', '-'][..]) {
+            let name = token.trim_end_matches(&['+', '-'][..]).trim();
+            if name.is_empty() {
+                return Err(eyre!("invalid preset selection '{}'", selection));
+            }
+            if is_excluded {
+                presets.exclude.push(name.to_string());
+            } else {
+                presets.include.push(name.to_string());
+            }
+            is_excluded = token.ends_with('-');
+        }
+        if presets.include.is_empty() || selection.ends_with(&['+', '-'][..]) {
+            return Err(eyre!("invalid preset selection '{}'", selection));
+        }
+        Ok(presets)
+    }
 }
 
 impl App {
@@ -205,10 +479,22 @@ impl App {
             ("{{exe}}", Some(&build.exe)),
             ("{{dll}}", build.dll.as_ref()),
         ];
+        let env_path = app_config
+            .env_path
+            .as_ref()
+            .map(|p| input_paths.apply_to(p));
         let tests = tests
             .into_iter()
             .map(|p| {
                 let command = p.command.unwrap_or_else(|| app_config.command.clone());
+                // environment variables are inherited per variable, lower levels win
+                let mut preset_env = app_config.env.clone();
+                preset_env.extend(p.env);
+                let preset_returncodes = p
+                    .accepted_returncodes
+                    .unwrap_or_else(|| app_config.accepted_returncodes.clone());
+                let mut preset_outcomes = app_config.returncode_outcomes.clone();
+                preset_outcomes.extend(p.returncode_outcomes);
 
                 let groups = p
                     .groups
@@ -227,16 +513,35 @@ impl App {
                             }
                         }
                         let command = command.apply_input_paths(input_paths);
+                        let mut env = preset_env.clone();
+                        env.extend(g.env);
+                        for value in env.values_mut() {
+                            *value = input_paths.apply_to(value);
+                        }
+                        if let Some(env_path) = &env_path {
+                            prepend_search_paths(&mut env, env_path);
+                        }
+                        let mut returncode_outcomes = preset_outcomes.clone();
+                        returncode_outcomes.extend(g.returncode_outcomes);
                         TestGroup {
                             command,
+                            env,
                             find_glob: g.find_glob,
+                            find_parent_dir: g.find_parent_dir,
                             find_gtest: g.find_gtest,
+                            input_strings: g.input_strings,
                             timeout: g.timeout,
                             timeout_if_changed: g.timeout_if_changed,
-                            accepted_returncodes: app_config.accepted_returncodes.to_vec(),
+                            accepted_returncodes: g
+                                .accepted_returncodes
+                                .unwrap_or_else(|| preset_returncodes.clone()),
+                            returncode_outcomes,
                             testcases_dependencies: g.testcases_dependencies,
                             execution_style: g.execution_style,
                             exclusion_list: g.exclusion_list,
+                            wrapper: g.wrapper.map(|w| w.apply_input_paths(input_paths)),
+                            wrapper_timeout_factor: g.wrapper_timeout_factor,
+                            resources: g.resources,
                         }
                     })
                     .collect();
@@ -253,24 +558,25 @@ impl App {
             globber_matches_parent: app_config.globber_matches_parent,
             checkout_parent: app_config.checkout_parent,
             supports_gtest_batching: app_config.supports_gtest_batching,
+            max_parallel: app_config.max_parallel,
         }
     }
 }
 
 impl Build {
     fn from(build: &BuildConfig, input_paths: &InputPaths) -> Build {
-        let exe = Build::apply_config_string(&build.exe, &input_paths).unwrap();
+        let exe = Build::apply_config_string(&build.exe, input_paths).unwrap();
         //if !PathBuf::from(&exe).exists() {
         //    println!("WARNING: exe not found: {:?}", exe);
         //}
-        let dll = Build::apply_config_string(&build.dll, &input_paths);
+        let dll = Build::apply_config_string(&build.dll, input_paths);
         //if let Some(dll) = &dll {
         //    if !PathBuf::from(&dll).exists() {
         //        println!("WARNING: dll not found: {:?}", dll);
         //    }
         //}
-        let cwd = Build::apply_config_string(&build.cwd, &input_paths);
-        let solution = Build::apply_config_string(&build.solution, &input_paths);
+        let cwd = Build::apply_config_string(&build.cwd, input_paths);
+        let solution = Build::apply_config_string(&build.solution, input_paths);
         Build {
             exe,
             dll,
@@ -281,10 +587,28 @@ impl Build {
     }
 
     fn apply_config_string(string: &Option<String>, input_paths: &InputPaths) -> Option<String> {
-        match &string {
-            Some(s) => Some(input_paths.apply_to(s)),
-            None => None,
-        }
+        string.as_ref().map(|s| input_paths.apply_to(s))
+    }
+}
+
+/// Prepends env_path to the variables that are used to find executables and shared libraries.
+fn prepend_search_paths(env: &mut HashMap<String, String>, env_path: &str) {
+    let vars: &[&str] = if cfg!(windows) {
+        &["PATH"]
+    } else {
+        &["PATH", "LD_LIBRARY_PATH"]
+    };
+    for var in vars {
+        let current = env
+            .get(*var)
+            .cloned()
+            .or_else(|| std::env::var(var).ok())
+            .unwrap_or_default();
+        let paths = std::env::split_paths(env_path)
+            .chain(std::env::split_paths(&current))
+            .filter(|p| !p.as_os_str().is_empty());
+        let joined = std::env::join_paths(paths).expect("invalid character in env_path!");
+        env.insert(var.to_string(), joined.to_string_lossy().into_owned());
     }
 }
 
@@ -300,49 +624,70 @@ fn value_xge() -> String {
 }
 
 impl TestGroup {
+    /// The environment of the test command, sorted by name.
+    pub fn env_vars(&self) -> Vec<(String, String)> {
+        let mut env: Vec<_> = self.env.clone().into_iter().collect();
+        env.sort();
+        env
+    }
+
     pub fn generate_test_inputs(
         &self,
         app: &App,
         preset: &TestPreset,
         input_paths: &InputPaths,
+        discovery: &GtestDiscovery,
     ) -> Vec<crate::TestId> {
-        if self.find_glob.is_some() {
-            let id_pattern = match &preset.id_pattern {
-                Some(p) => &p,
-                None => "(.*)",
-            };
-            self.generate_path_inputs(&app, id_pattern, &input_paths)
-        } else if self.find_gtest.is_some() {
-            self.generate_gtest_inputs(&app)
+        let id_pattern = match &preset.id_pattern {
+            Some(p) => p,
+            None => "(.*)",
+        };
+        if let Some(find_glob) = &self.find_glob {
+            // globber_matches_parent is the app-wide predecessor of find_parent_dir
+            let match_parent = app.globber_matches_parent;
+            TestGroup::generate_path_inputs(find_glob, match_parent, id_pattern, input_paths)
+        } else if let Some(find_parent_dir) = &self.find_parent_dir {
+            TestGroup::generate_path_inputs(find_parent_dir, true, id_pattern, input_paths)
+        } else if let Some(request) = self.gtest_request(app) {
+            discovery
+                .tests(&request)
+                .into_iter()
+                .map(|id| crate::TestId { id, rel_path: None })
+                .collect()
+        } else if self.input_strings.is_some() {
+            self.generate_string_inputs()
         } else {
             panic!("no test generator defined!");
         }
     }
 
     fn generate_path_inputs(
-        &self,
-        test_config: &App,
+        glob: &str,
+        match_parent: bool,
         id_pattern: &str,
         input_paths: &InputPaths,
     ) -> Vec<crate::TestId> {
-        let re = regex::Regex::new(&id_pattern).unwrap();
+        let re = regex::Regex::new(id_pattern).unwrap();
         // the glob module can't handle Windows' extended path syntax
         let abs_path = input_paths
             .testcases_dir
-            .join(self.find_glob.clone().unwrap())
+            .join(glob)
             .to_str()
             .unwrap()
             .to_string();
+        // several marker files may share a parent directory, but each directory is only one test
+        let mut seen = HashSet::new();
         glob::glob(&abs_path)
             .expect("failed to read glob pattern!")
             .map(Result::unwrap)
             .map(|p| {
-                if test_config.globber_matches_parent {
+                if match_parent {
                     PathBuf::from(p.parent().unwrap())
                 } else {
                     p
                 }
             })
+            .filter(|p| seen.insert(p.clone()))
             .map(|p| {
                 p.strip_prefix(&input_paths.testcases_dir)
                     .unwrap()
@@ -368,49 +713,28 @@ impl TestGroup {
             })
             .collect()
     }
-    fn generate_gtest_inputs(&self, app: &App) -> Vec<crate::TestId> {
-        let filter = self.find_gtest.clone().unwrap();
-        let exe = &app.build.exe;
-        if !PathBuf::from(exe).exists() {
-            println!(
-                "Could not find GTest executable at {}!\nDid you forget to build?",
-                exe
-            );
-            std::process::exit(-1);
-        }
-        let args = self.command.clone().apply("{{input}}", &filter);
-        let args = &args.0[1..];
-        let cwd = app.build.cwd.as_ref().map(|s| s.as_ref()).unwrap_or(".");
-        let output = std::process::Command::new(exe)
-            .arg("--gtest_list_tests")
-            .args(args)
-            .current_dir(cwd)
-            .output()
-            .expect("failed to gather tests!");
-        if !output.status.success() {
-            println!("Failed to execute {} {:?}: {:?}", exe, args, output);
-            std::process::exit(-1);
-        }
-        let output: &str = std::str::from_utf8(&output.stdout)
-            .expect("could not decode find_gtest output as utf-8!");
-        let mut group = String::new();
-        let mut results = Vec::new();
-        for line in output
-            .lines()
-            .filter(|l| !l.contains("DISABLED"))
-            .map(|l| l.split('#').next().unwrap())
-        {
-            if !line.starts_with(' ') {
-                group = line.trim().to_string();
-            } else {
-                let test_id = group.clone() + line.trim();
-                results.push(crate::TestId {
-                    id: test_id,
-                    rel_path: None,
-                });
-            }
-        }
-        results
+    fn generate_string_inputs(&self) -> Vec<crate::TestId> {
+        self.input_strings
+            .iter()
+            .flatten()
+            .map(|s| crate::TestId {
+                id: s.to_string(),
+                rel_path: None,
+            })
+            .collect()
+    }
+
+    /// The `--gtest_list_tests` call that discovers the tests of a find_gtest group.
+    pub fn gtest_request(&self, app: &App) -> Option<GtestRequest> {
+        let filter = self.find_gtest.as_ref()?;
+        // extra args are meant for running the tests, not for listing them
+        let args = self.command.with_extra_args(&[]).apply("{{input}}", filter);
+        Some(GtestRequest {
+            exe: app.build.exe.clone(),
+            args: args.0[1..].to_vec(),
+            cwd: app.build.cwd.clone().unwrap_or_else(|| ".".to_string()),
+            env: self.env.clone(),
+        })
     }
 }
 
@@ -426,6 +750,26 @@ impl CommandTemplate {
     pub fn apply_input_paths(&self, input_paths: &InputPaths) -> CommandTemplate {
         CommandTemplate(self.0.iter().map(|t| input_paths.apply_to(t)).collect())
     }
+    /// Inserts the arguments given after `--` at `{{extra_args}}`, or appends them if the
+    /// placeholder isn't used.
+    pub fn with_extra_args(&self, extra_args: &[String]) -> CommandTemplate {
+        if !self.has_pattern("{{extra_args}}") {
+            return CommandTemplate(self.0.iter().chain(extra_args).cloned().collect());
+        }
+        let mut tokens = vec![];
+        for t in &self.0 {
+            if t == "{{extra_args}}" {
+                tokens.extend(extra_args.iter().cloned());
+            } else {
+                tokens.push(t.replace("{{extra_args}}", &extra_args.join(" ")));
+            }
+        }
+        CommandTemplate(tokens)
+    }
+    /// Runs this command through `wrapper`, like valgrind or a profiler.
+    pub fn with_wrapper(&self, wrapper: &CommandTemplate) -> CommandTemplate {
+        CommandTemplate(wrapper.0.iter().chain(&self.0).cloned().collect())
+    }
     pub fn has_pattern(&self, pattern: &str) -> bool {
         self.0.iter().any(|t| t.contains(pattern))
     }
@@ -439,7 +783,143 @@ pub struct InputPaths {
     pub build_type: Option<String>,
     pub preset: String,
     pub build_config: String,
+    /// single-config CMake generators (like Ninja) don't put binaries in a folder per config
+    pub single_config: bool,
+    pub sources: InputSources,
 }
+
+/// Where each value of InputPaths came from (shown by "mwtest info").
+#[derive(Debug)]
+pub struct InputSources {
+    pub dev_dir: ValueSource,
+    pub build_dir: ValueSource,
+    pub testcases_dir: ValueSource,
+    pub build_type: ValueSource,
+    pub preset: ValueSource,
+    pub build_config: ValueSource,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueSource {
+    CommandLine,
+    File(PathBuf),
+    Detected,
+    Default,
+}
+impl std::fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ValueSource::CommandLine => write!(f, "command line"),
+            ValueSource::File(path) => write!(f, "{}", path.display()),
+            ValueSource::Detected => write!(f, "detected"),
+            ValueSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+// Defaults for the command line options, read from a project-local .mwtest.toml (found by
+// walking up from the current directory) and from the per-user config file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DefaultsConfig {
+    dev_dir: Option<String>,
+    build_dir: Option<String>,
+    testcases_dir: Option<String>,
+    build_type: Option<String>,
+    preset: Option<String>,
+    config: Option<String>,
+    debugger: Option<String>,
+    /// capacities of the resources that test groups hold, like { license = 2 }
+    #[serde(default)]
+    resources: HashMap<String, usize>,
+}
+impl DefaultsConfig {
+    /// Returns all existing defaults files, the ones with the highest priority first.
+    fn load_all() -> Result<Vec<(DefaultsConfig, PathBuf)>> {
+        DefaultsConfig::load_all_from(&std::env::current_dir()?, dirs::config_dir())
+    }
+
+    /// Like load_all, with the directory to search upwards from and the user's config directory.
+    fn load_all_from(
+        start_dir: &Path,
+        config_dir: Option<PathBuf>,
+    ) -> Result<Vec<(DefaultsConfig, PathBuf)>> {
+        let project_file = start_dir
+            .ancestors()
+            .map(|d| d.join(".mwtest.toml"))
+            .find(|f| f.is_file());
+        let user_file = config_dir
+            .map(|d| d.join("mwtest").join("config.toml"))
+            .filter(|f| f.is_file());
+        project_file
+            .into_iter()
+            .chain(user_file)
+            .map(|f| Ok((DefaultsConfig::load(&f)?, f)))
+            .collect()
+    }
+
+    fn load(path: &Path) -> Result<DefaultsConfig> {
+        let content = std::fs::read_to_string(path)?;
+        let mut defaults: DefaultsConfig =
+            toml::from_str(&content).map_err(|e| eyre!("{}: {}", path.display(), e))?;
+        // relative paths are relative to the file that contains them
+        let dir = path.parent().unwrap();
+        for p in [
+            &mut defaults.dev_dir,
+            &mut defaults.build_dir,
+            &mut defaults.testcases_dir,
+        ]
+        .iter_mut()
+        .filter_map(|p| p.as_mut())
+        {
+            *p = dir.join(&p).to_str().unwrap().to_string();
+        }
+        Ok(defaults)
+    }
+
+    /// The value from the command line, otherwise from the first defaults file that has it.
+    fn given_or_default(
+        given: Option<String>,
+        defaults: &[(DefaultsConfig, PathBuf)],
+        field: fn(&DefaultsConfig) -> &Option<String>,
+    ) -> Option<(String, ValueSource)> {
+        match given {
+            Some(value) => Some((value, ValueSource::CommandLine)),
+            None => defaults.iter().find_map(|(d, file)| {
+                field(d)
+                    .clone()
+                    .map(|value| (value, ValueSource::File(file.clone())))
+            }),
+        }
+    }
+}
+
+/// The debugger for "mwtest debug" (like "gdb" or "lldb", optionally with arguments), from
+/// $MWTEST_DEBUGGER or the defaults files.
+pub fn configured_debugger() -> Result<Option<String>> {
+    if let Ok(debugger) = std::env::var("MWTEST_DEBUGGER") {
+        if !debugger.trim().is_empty() {
+            return Ok(Some(debugger));
+        }
+    }
+    Ok(DefaultsConfig::load_all()?
+        .into_iter()
+        .find_map(|(defaults, _)| defaults.debugger))
+}
+
+/// Capacities of resources from the defaults files. Resources that aren't listed have a capacity
+/// of 1.
+pub fn resource_capacities() -> Result<HashMap<String, usize>> {
+    let mut capacities = HashMap::new();
+    for (defaults, _) in DefaultsConfig::load_all()? {
+        for (name, capacity) in defaults.resources {
+            // the first file has the highest priority
+            capacities.entry(name).or_insert(capacity);
+        }
+    }
+    Ok(capacities)
+}
+
 impl InputPaths {
     fn apply_to(&self, string: &str) -> String {
         let mut s = string.to_string();
@@ -459,6 +939,13 @@ impl InputPaths {
         if s.contains("{{testcases_dir}}") {
             s = s.replace("{{testcases_dir}}", self.testcases_dir.to_str().unwrap());
         }
+        if self.single_config {
+            s = s.replace("{{build_config_dir}}/", "");
+            s = s.replace("{{build_config_dir}}\\", "");
+            s = s.replace("{{build_config_dir}}", "");
+        } else {
+            s = s.replace("{{build_config_dir}}", &self.build_config);
+        }
         s = s.replace("{{build_config}}", &self.build_config);
         s = s.replace(
             "{{build_config_skipunicode}}",
@@ -475,19 +962,44 @@ impl InputPaths {
         given_preset: Option<String>,
         given_build_config: Option<String>,
     ) -> Result<InputPaths> {
+        let defaults = DefaultsConfig::load_all()?;
+        let with_default =
+            |given: Option<String>, field: fn(&DefaultsConfig) -> &Option<String>| {
+                DefaultsConfig::given_or_default(given, &defaults, field)
+            };
+        let given_dev_dir = with_default(given_dev_dir, |d| &d.dev_dir);
+        let given_build_dir = with_default(given_build_dir, |d| &d.build_dir);
+        let given_testcases_dir = with_default(given_testcases_dir, |d| &d.testcases_dir);
+        let given_build_type = with_default(given_build_type, |d| &d.build_type);
+        let given_preset = with_default(given_preset, |d| &d.preset);
+        let given_build_config = with_default(given_build_config, |d| &d.config);
+
         let dev_dir: Option<PathBuf>;
         let build_dir: Option<PathBuf>;
         let build_type: Option<&str>;
-        match InputPaths::guess_build_type(&given_build_dir) {
-            BuildType::CMake(build_path, dev_path) => {
-                dev_dir = Some(dev_path);
-                build_dir = Some(build_path);
-                build_type = Some("cmake-windows");
+        let mut build_config: Option<String> = None;
+        let mut cmake_configs: Option<CMakeConfigs> = None;
+        let build_dir_str = given_build_dir.as_ref().map(|(d, _)| d.clone());
+        match InputPaths::guess_build_type(&build_dir_str) {
+            BuildType::CMake(layout) => {
+                dev_dir = Some(layout.dev_dir);
+                build_dir = Some(layout.build_dir);
+                build_type = Some(if layout.is_linux {
+                    "cmake-linux"
+                } else {
+                    "cmake-windows"
+                });
+                build_config = layout.configs.default_config();
+                cmake_configs = Some(layout.configs);
             }
-            BuildType::Quickstart(path) => {
+            BuildType::Quickstart(path, is_linux) => {
                 dev_dir = None;
                 build_dir = Some(path);
-                build_type = Some("quickstart");
+                build_type = Some(if is_linux {
+                    "quickstart-linux"
+                } else {
+                    "quickstart"
+                });
             }
             BuildType::None => {
                 dev_dir = None;
@@ -495,28 +1007,46 @@ impl InputPaths {
                 build_type = None;
             }
         }
-        let dev_dir = given_dev_dir.map(PathBuf::from).or(dev_dir);
-        let build_dir = given_build_dir
-            .map(PathBuf::from)
-            .or(build_dir)
+        let (dev_dir, dev_dir_source) =
+            InputPaths::given_or_detected(given_dev_dir, dev_dir, PathBuf::from);
+        let (build_dir, build_dir_source) =
+            InputPaths::given_or_detected(given_build_dir, build_dir, PathBuf::from);
+        let build_dir = build_dir
             .wrap_err("Could not determine --build-dir. You may have to specify it explicitly.")?;
-        let build_type = given_build_type.or_else(|| build_type.map(|s| s.to_string()));
-
-        let testcases_dir = given_testcases_dir
-            .map(PathBuf::from)
-            .or_else(|| InputPaths::guess_testcases_layout(&build_dir))
-            .wrap_err(
-                "Could not determine --testcases-dir. You may have to specify it explicitly.",
-            )?;
+        let (build_type, build_type_source) = InputPaths::given_or_detected(
+            given_build_type,
+            build_type.map(|s| s.to_string()),
+            |s| s,
+        );
 
-        let preset = given_preset.unwrap_or_else(|| "ci".to_string());
+        let (testcases_dir, testcases_dir_source) = InputPaths::given_or_detected(
+            given_testcases_dir,
+            InputPaths::guess_testcases_layout(&build_dir),
+            PathBuf::from,
+        );
+        let testcases_dir = testcases_dir.wrap_err(
+            "Could not determine --testcases-dir. You may have to specify it explicitly.",
+        )?;
 
-        // println!("dev_dir: {:?}", dev_dir);
-        // println!("build_dir: {:?}", build_dir);
-        // println!("build_type: {:?}", build_type);
-        // println!("testcases_dir: {:?}", testcases_dir);
+        let (preset, preset_source) =
+            given_preset.unwrap_or_else(|| ("ci".to_string(), ValueSource::Default));
 
-        let build_config = given_build_config.unwrap_or_else(|| "RelWithDebInfo".to_string());
+        let (build_config, build_config_source) =
+            match InputPaths::given_or_detected(given_build_config, build_config, |s| s) {
+                (Some(build_config), source) => (build_config, source),
+                (None, _) => ("RelWithDebInfo".to_string(), ValueSource::Default),
+            };
+        let build_config = match &cmake_configs {
+            Some(configs) => configs.canonical(&build_config)?,
+            None => build_config,
+        };
+        let single_config = matches!(
+            cmake_configs,
+            Some(CMakeConfigs {
+                single_config: true,
+                ..
+            })
+        );
 
         Ok(InputPaths {
             dev_dir,
@@ -525,20 +1055,41 @@ impl InputPaths {
             build_type,
             preset,
             build_config,
+            single_config,
+            sources: InputSources {
+                dev_dir: dev_dir_source,
+                build_dir: build_dir_source,
+                testcases_dir: testcases_dir_source,
+                build_type: build_type_source,
+                preset: preset_source,
+                build_config: build_config_source,
+            },
         })
     }
 
+    fn given_or_detected<T>(
+        given: Option<(String, ValueSource)>,
+        detected: Option<T>,
+        convert: fn(String) -> T,
+    ) -> (Option<T>, ValueSource) {
+        match (given, detected) {
+            (Some((value, source)), _) => (Some(convert(value)), source),
+            (None, Some(value)) => (Some(value), ValueSource::Detected),
+            (None, None) => (None, ValueSource::Default),
+        }
+    }
+
     fn guess_build_type(build_dir: &Option<String>) -> BuildType {
         if let Some(layout) = InputPaths::find_cmake_layout(build_dir) {
-            layout
-        } else if InputPaths::is_quickstart(build_dir) {
-            BuildType::Quickstart(std::env::current_dir().unwrap())
+            BuildType::CMake(layout)
+        } else if let Some(is_linux) = InputPaths::find_quickstart(build_dir) {
+            BuildType::Quickstart(std::env::current_dir().unwrap(), is_linux)
         } else {
             BuildType::None
         }
     }
 
-    fn guess_testcases_layout(build_dir: &PathBuf) -> Option<PathBuf> {
+    fn guess_testcases_layout(build_dir: &Path) -> Option<PathBuf> {
         let guessed_path = build_dir.parent()?.join("testcases");
         if guessed_path.exists() {
             Some(guessed_path)
@@ -547,37 +1098,532 @@ impl InputPaths {
         }
     }
 
-    fn find_cmake_layout(build_dir: &Option<String>) -> Option<BuildType> {
+    fn find_cmake_layout(build_dir: &Option<String>) -> Option<CMakeLayout> {
         let path = PathBuf::from(build_dir.clone().unwrap_or_default()).join("CMakeCache.txt");
-        if let Ok(f) = std::fs::File::open(path) {
-            let mut reader = std::io::BufReader::new(f);
-            let mut line = String::new();
-            while let Ok(count) = reader.read_line(&mut line) {
-                if count == 0 {
-                    break;
-                }
-                if let Some(dev_path) = line.split("mwBuildAll_SOURCE_DIR:STATIC=").nth(1) {
-                    return Some(BuildType::CMake(
-                        std::env::current_dir().unwrap(),
-                        PathBuf::from(dev_path.trim()),
-                    ));
-                }
-            }
-        }
-        None
+        let cache = read_cmake_cache(&path)?;
+        let dev_dir = cache.get("mwBuildAll_SOURCE_DIR")?;
+        // Visual Studio solutions are only generated on Windows, Ninja and Makefiles are used
+        // on both
+        let is_linux = match cache.get("CMAKE_GENERATOR") {
+            Some(generator) if generator.starts_with("Visual Studio") => false,
+            _ => !cfg!(windows),
+        };
+        Some(CMakeLayout {
+            build_dir: std::env::current_dir().unwrap(),
+            dev_dir: PathBuf::from(dev_dir),
+            is_linux,
+            configs: CMakeConfigs::from_cache(&cache),
+        })
     }
 
-    fn is_quickstart(build_dir: &Option<String>) -> bool {
+    /// Returns whether the quickstart in the build dir contains Linux (instead of Windows)
+    /// libraries.
+    fn find_quickstart(build_dir: &Option<String>) -> Option<bool> {
         let cwd = build_dir
             .as_ref()
             .map(PathBuf::from)
             .unwrap_or_else(|| std::env::current_dir().unwrap());
-        cwd.join("mwVerifier.dll").exists()
+        if cwd.join("mwVerifier.dll").exists() {
+            Some(false)
+        } else if cwd.join("libmwVerifier.so").exists() {
+            Some(true)
+        } else {
+            None
+        }
+    }
+}
+
+/// Reads the "KEY:TYPE=VALUE" entries of a CMakeCache.txt.
+fn read_cmake_cache(path: &Path) -> Option<HashMap<String, String>> {
+    Some(parse_cmake_cache(&std::fs::read_to_string(path).ok()?))
+}
+
+fn parse_cmake_cache(content: &str) -> HashMap<String, String> {
+    let mut entries = HashMap::new();
+    for line in content.lines() {
+        if line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let key = key.split(':').next().unwrap();
+            entries.insert(key.to_string(), value.trim().to_string());
+        }
+    }
+    entries
+}
+
+struct CMakeLayout {
+    build_dir: PathBuf,
+    dev_dir: PathBuf,
+    is_linux: bool,
+    configs: CMakeConfigs,
+}
+
+/// The configs a CMake build dir was generated for.
+struct CMakeConfigs {
+    single_config: bool,
+    /// CMAKE_CONFIGURATION_TYPES, or CMAKE_BUILD_TYPE for single-config generators
+    available: Vec<String>,
+}
+impl CMakeConfigs {
+    fn from_cache(cache: &HashMap<String, String>) -> CMakeConfigs {
+        let configuration_types = cache
+            .get("CMAKE_CONFIGURATION_TYPES")
+            .filter(|t| !t.is_empty());
+        match configuration_types {
+            Some(types) => CMakeConfigs {
+                single_config: false,
+                available: types.split(';').map(|t| t.to_string()).collect(),
+            },
+            None => CMakeConfigs {
+                single_config: true,
+                available: cache
+                    .get("CMAKE_BUILD_TYPE")
+                    .filter(|t| !t.is_empty())
+                    .cloned()
+                    .into_iter()
+                    .collect(),
+            },
+        }
+    }
+
+    fn default_config(&self) -> Option<String> {
+        if self.single_config || !self.available.iter().any(|c| c == "RelWithDebInfo") {
+            self.available.first().cloned()
+        } else {
+            None
+        }
+    }
+
+    /// The config as it is spelled in the cache, because it becomes part of paths (like
+    /// "--config release" for the "Release" folder).
+    fn canonical(&self, build_config: &str) -> Result<String> {
+        if let Some(config) = self
+            .available
+            .iter()
+            .find(|c| c.eq_ignore_ascii_case(build_config))
+        {
+            Ok(config.clone())
+        } else if self.available.is_empty() {
+            // nothing is known about single-config builds without CMAKE_BUILD_TYPE
+            Ok(build_config.to_string())
+        } else if self.single_config {
+            Err(eyre!(
+                "The build dir was configured for '{}' only (CMAKE_BUILD_TYPE), not for '{}'.",
+                self.available[0],
+                build_config
+            ))
+        } else {
+            Err(eyre!(
+                "The build dir has no config '{}' (available: {}).",
+                build_config,
+                self.available.join(", ")
+            ))
+        }
     }
 }
 
 enum BuildType {
-    CMake(PathBuf, PathBuf),
-    Quickstart(PathBuf),
+    CMake(CMakeLayout),
+    /// also stores whether this is a Linux quickstart
+    Quickstart(PathBuf, bool),
     None,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An apps.json fragment with minimal apps, `extra` is added to each app's keys.
+    fn fragment(apps: &[(&str, &str)], path: &str) -> (AppsConfig, PathBuf) {
+        let apps: Vec<_> = apps
+            .iter()
+            .map(|(name, extra)| {
+                format!(
+                    r#""{}": {{ "command": ["{{{{exe}}}}"], "responsible": "me", "builds": {{}},
+                        "tests": {{}} {} }}"#,
+                    name, extra
+                )
+            })
+            .collect();
+        let json = format!("{{ {} }}", apps.join(", "));
+        (serde_json::from_str(&json).unwrap(), PathBuf::from(path))
+    }
+
+    #[test]
+    fn merge_disjoint_fragments() {
+        let merged = AppsConfig::merge(vec![
+            fragment(&[("a", r#", "alias": ["x"], "tags": ["t1"]"#)], "a.json"),
+            fragment(&[("b", r#", "alias": ["y"], "tags": ["t2"]"#)], "b.json"),
+        ])
+        .unwrap();
+        assert_eq!(merged.app_names(), ["a", "b"]);
+    }
+
+    #[test]
+    fn merge_rejects_duplicate_apps() {
+        let error = AppsConfig::merge(vec![
+            fragment(&[("a", "")], "first.json"),
+            fragment(&[("b", "")], "second.json"),
+            fragment(&[("A", "")], "third.json"),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert_eq!(error, "'a' is defined in both first.json and third.json");
+    }
+
+    #[test]
+    fn merge_rejects_shared_aliases_and_tags() {
+        let fragments = vec![
+            fragment(
+                &[("a", r#", "alias": ["x"], "tags": ["nightly"]"#)],
+                "a.json",
+            ),
+            fragment(&[("b", r#", "tags": ["nightly"]"#)], "b.json"),
+            fragment(&[("c", r#", "tags": ["x"]"#)], "c.json"),
+        ];
+        assert_eq!(
+            AppsConfig::find_duplicates(&fragments),
+            [
+                ("nightly".to_string(), "a.json".into(), "b.json".into()),
+                ("x".to_string(), "a.json".into(), "c.json".into()),
+            ]
+        );
+        assert!(AppsConfig::merge(fragments).is_err());
+
+        // apps of the same fragment can share a tag
+        let same_file = fragment(
+            &[("a", r#", "tags": ["t"]"#), ("b", r#", "tags": ["t"]"#)],
+            "one.json",
+        );
+        assert!(AppsConfig::merge(vec![same_file]).is_ok());
+    }
+
+    #[test]
+    fn defaults_precedence() {
+        let root = std::env::temp_dir().join(format!("mwtest_defaults_{}", std::process::id()));
+        let project = root.join("project");
+        let nested = project.join("a").join("b");
+        let config_dir = root.join("config");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir_all(config_dir.join("mwtest")).unwrap();
+        std::fs::write(
+            project.join(".mwtest.toml"),
+            "build_dir = \"build\"\npreset = \"nightly\"\n",
+        )
+        .unwrap();
+        let user_file = config_dir.join("mwtest").join("config.toml");
+        std::fs::write(
+            &user_file,
+            "preset = \"ci\"\nbuild_type = \"cmake-linux\"\n",
+        )
+        .unwrap();
+
+        // the project file is found from subdirectories and comes first
+        let defaults = DefaultsConfig::load_all_from(&nested, Some(config_dir.clone())).unwrap();
+        let files: Vec<_> = defaults.iter().map(|(_, f)| f.clone()).collect();
+        assert_eq!(files, [project.join(".mwtest.toml"), user_file.clone()]);
+        let value = |given: Option<&str>, field: fn(&DefaultsConfig) -> &Option<String>| {
+            DefaultsConfig::given_or_default(given.map(String::from), &defaults, field)
+        };
+        assert_eq!(
+            value(None, |d| &d.preset),
+            Some((
+                "nightly".to_string(),
+                ValueSource::File(project.join(".mwtest.toml"))
+            ))
+        );
+        // keys missing in the project file come from the user's file
+        assert_eq!(
+            value(None, |d| &d.build_type),
+            Some(("cmake-linux".to_string(), ValueSource::File(user_file)))
+        );
+        // the command line wins
+        assert_eq!(
+            value(Some("weekly"), |d| &d.preset),
+            Some(("weekly".to_string(), ValueSource::CommandLine))
+        );
+        assert_eq!(value(None, |d| &d.config), None);
+        // relative paths are relative to the file
+        assert_eq!(
+            value(None, |d| &d.build_dir).unwrap().0,
+            project.join("build").to_str().unwrap()
+        );
+
+        // without a project file, only the user's file is used
+        let defaults = DefaultsConfig::load_all_from(&root, Some(config_dir)).unwrap();
+        assert_eq!(defaults.len(), 1);
+        assert!(DefaultsConfig::load_all_from(&root, None)
+            .unwrap()
+            .is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    fn input_paths(preset: &str) -> InputPaths {
+        InputPaths {
+            dev_dir: None,
+            build_dir: PathBuf::from("/build"),
+            testcases_dir: PathBuf::from("/testcases"),
+            build_type: Some("b".to_string()),
+            preset: preset.to_string(),
+            build_config: "Release".to_string(),
+            single_config: false,
+            sources: InputSources {
+                dev_dir: ValueSource::Default,
+                build_dir: ValueSource::Default,
+                testcases_dir: ValueSource::Default,
+                build_type: ValueSource::Default,
+                preset: ValueSource::Default,
+                build_config: ValueSource::Default,
+            },
+        }
+    }
+
+    #[test]
+    fn env_is_overridden_per_variable() {
+        let apps: AppsConfig = serde_json::from_str(
+            r#"{ "a": { "command": ["{{exe}}"], "responsible": "me",
+                 "builds": { "b": { "exe": "x" } },
+                 "env": { "APP": "app", "PRESET": "app", "GROUP": "app" },
+                 "tests": { "ci": {
+                     "env": { "PRESET": "preset", "GROUP": "preset" },
+                     "groups": [ { "input_strings": ["i"],
+                                   "env": { "GROUP": "{{build_dir}}/group" } } ] } } } }"#,
+        )
+        .unwrap();
+        let apps = apps
+            .select_build_and_preset(&["a".to_string()], &input_paths("ci"))
+            .unwrap();
+        let group = &apps.0["a"].tests[0].groups[0];
+        assert_eq!(
+            group.env_vars(),
+            [
+                ("APP".to_string(), "app".to_string()),
+                ("GROUP".to_string(), "/build/group".to_string()),
+                ("PRESET".to_string(), "preset".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn env_path_is_prepended() {
+        let mut env = HashMap::new();
+        env.insert("PATH".to_string(), "/group/bin".to_string());
+        let env_path = std::env::join_paths(["/a", "/b"].iter()).unwrap();
+        prepend_search_paths(&mut env, env_path.to_str().unwrap());
+        let path: Vec<_> = std::env::split_paths(&env["PATH"]).collect();
+        assert_eq!(
+            path,
+            [
+                PathBuf::from("/a"),
+                PathBuf::from("/b"),
+                PathBuf::from("/group/bin")
+            ]
+        );
+        if cfg!(not(windows)) {
+            // without a value in the group, the current one is extended
+            let current = std::env::var("LD_LIBRARY_PATH").unwrap_or_default();
+            let library_path: Vec<_> = std::env::split_paths(&env["LD_LIBRARY_PATH"]).collect();
+            let mut expected = vec![PathBuf::from("/a"), PathBuf::from("/b")];
+            expected.extend(std::env::split_paths(&current).filter(|p| !p.as_os_str().is_empty()));
+            assert_eq!(library_path, expected);
+        }
+    }
+
+    fn preset_apps() -> AppsConfig {
+        serde_json::from_str(
+            r#"{ "a": { "command": ["{{exe}}"], "responsible": "me",
+                 "builds": { "b": { "exe": "x" } },
+                 "tests": {
+                     "ci": { "groups": [ { "input_strings": ["ci"] } ] },
+                     "nightly": { "extends": ["ci"],
+                                  "groups": [ { "input_strings": ["nightly"] } ] },
+                     "weekly": { "extends": ["nightly"],
+                                 "groups": [ { "input_strings": ["weekly"] } ] },
+                     "perf": { "groups": [ { "input_strings": ["perf"] } ] },
+                     "loop1": { "extends": ["loop2"], "groups": [] },
+                     "loop2": { "extends": ["loop1"], "groups": [] },
+                     "broken": { "extends": ["missing"], "groups": [] } } } }"#,
+        )
+        .unwrap()
+    }
+
+    /// The inputs of all groups that the preset selection runs.
+    fn selected_inputs(preset: &str) -> Result<Vec<String>> {
+        let mut input_paths = input_paths(preset);
+        input_paths.sources.preset = ValueSource::CommandLine;
+        let apps = preset_apps().select_build_and_preset(&["a".to_string()], &input_paths)?;
+        Ok(apps.0["a"]
+            .tests
+            .iter()
+            .flat_map(|p| &p.groups)
+            .flat_map(|g| g.input_strings.clone().unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn preset_selection() {
+        assert_eq!(selected_inputs("ci").unwrap(), ["ci"]);
+        assert_eq!(selected_inputs("ci+perf").unwrap(), ["ci", "perf"]);
+        // presets are only run once
+        assert_eq!(selected_inputs("ci+nightly").unwrap(), ["ci", "nightly"]);
+        assert_eq!(
+            selected_inputs("weekly").unwrap(),
+            ["weekly", "nightly", "ci"]
+        );
+        assert_eq!(selected_inputs("weekly-ci").unwrap(), ["weekly", "nightly"]);
+        // removing a preset removes the presets it extends as well
+        assert_eq!(
+            selected_inputs("weekly+perf-nightly").unwrap(),
+            ["weekly", "perf"]
+        );
+    }
+
+    #[test]
+    fn preset_errors() {
+        let error = |preset: &str| format!("{:#}", selected_inputs(preset).unwrap_err());
+        assert_eq!(
+            error("loop1"),
+            "a: preset 'loop1' extends itself (loop1 -> loop2 -> loop1)"
+        );
+        assert_eq!(error("broken"), "a: preset 'missing' is not defined");
+        assert_eq!(
+            error("ci+typo"),
+            "preset 'typo' is not defined for any of the selected apps"
+        );
+        assert_eq!(error("ci+"), "invalid preset selection 'ci+'");
+        assert_eq!(error("-ci"), "invalid preset selection '-ci'");
+    }
+
+    #[test]
+    fn default_preset_may_be_missing() {
+        let apps: AppsConfig = serde_json::from_str(
+            r#"{ "perf_only": { "command": ["{{exe}}"], "responsible": "me",
+                 "builds": { "b": { "exe": "x" } },
+                 "tests": { "perf": { "groups": [ { "input_strings": ["perf"] } ] } } } }"#,
+        )
+        .unwrap();
+        let names = ["perf_only".to_string()];
+        let mut input_paths = input_paths("ci");
+        let selected = apps
+            .clone()
+            .select_build_and_preset(&names, &input_paths)
+            .unwrap();
+        assert!(selected.0["perf_only"].tests.is_empty());
+
+        input_paths.sources.preset = ValueSource::CommandLine;
+        let error = apps
+            .clone()
+            .select_build_and_preset(&names, &input_paths)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "preset 'ci' is not defined for any of the selected apps"
+        );
+        // "build" and "info" don't use the preset
+        assert!(apps.select_build(&names, &input_paths).is_ok());
+    }
+
+    #[test]
+    fn parent_dir_inputs() {
+        let testcases_dir =
+            std::env::temp_dir().join(format!("mwtest_parent_dir_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&testcases_dir);
+        for marker in &[
+            "suite/case1/a.marker",
+            "suite/case1/b.marker",
+            "suite/case2/a.marker",
+        ] {
+            let marker = testcases_dir.join(marker);
+            std::fs::create_dir_all(marker.parent().unwrap()).unwrap();
+            std::fs::write(marker, "").unwrap();
+        }
+        let mut input_paths = input_paths("ci");
+        input_paths.testcases_dir = testcases_dir.clone();
+
+        // both markers of case1 are one test
+        let ids =
+            TestGroup::generate_path_inputs("suite/*/*.marker", true, "suite/(.*)", &input_paths);
+        std::fs::remove_dir_all(&testcases_dir).unwrap();
+        let ids: Vec<_> = ids
+            .iter()
+            .map(|id| (id.id.as_str(), id.rel_path.clone().unwrap()))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("case1", PathBuf::from("suite/case1")),
+                ("case2", PathBuf::from("suite/case2"))
+            ]
+        );
+    }
+
+    #[test]
+    fn cmake_cache_entries() {
+        let cache = parse_cmake_cache(
+            "# This is the CMakeCache file.\n\
+             //Semicolon separated list of supported configuration types\n\
+             CMAKE_CONFIGURATION_TYPES:STRING=Debug;Release\n\
+             CMAKE_GENERATOR:INTERNAL=Ninja Multi-Config\n\
+             CMAKE_CXX_FLAGS:STRING=-DNAME=value -DPATH=C:/x\n\
+             CMAKE_BUILD_TYPE:STRING=\n\
+             UNTYPED=1\n\
+             \n",
+        );
+        assert_eq!(cache["CMAKE_CONFIGURATION_TYPES"], "Debug;Release");
+        assert_eq!(cache["CMAKE_GENERATOR"], "Ninja Multi-Config");
+        assert_eq!(cache["CMAKE_CXX_FLAGS"], "-DNAME=value -DPATH=C:/x");
+        assert_eq!(cache["CMAKE_BUILD_TYPE"], "");
+        assert_eq!(cache["UNTYPED"], "1");
+        assert_eq!(cache.len(), 5);
+    }
+
+    fn cmake_configs(entries: &[(&str, &str)]) -> CMakeConfigs {
+        let cache = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        CMakeConfigs::from_cache(&cache)
+    }
+
+    #[test]
+    fn cmake_multi_config() {
+        let configs = cmake_configs(&[
+            ("CMAKE_CONFIGURATION_TYPES", "Debug;Release;RelWithDebInfo"),
+            ("CMAKE_BUILD_TYPE", "Debug"),
+        ]);
+        assert!(!configs.single_config);
+        assert_eq!(configs.available, ["Debug", "Release", "RelWithDebInfo"]);
+        // the usual default is available
+        assert_eq!(configs.default_config(), None);
+        assert_eq!(configs.canonical("release").unwrap(), "Release");
+        assert_eq!(
+            configs.canonical("Profile").unwrap_err().to_string(),
+            "The build dir has no config 'Profile' (available: Debug, Release, RelWithDebInfo)."
+        );
+
+        let configs = cmake_configs(&[("CMAKE_CONFIGURATION_TYPES", "Debug;Release")]);
+        assert_eq!(configs.default_config().as_deref(), Some("Debug"));
+    }
+
+    #[test]
+    fn cmake_single_config() {
+        let configs = cmake_configs(&[
+            ("CMAKE_CONFIGURATION_TYPES", ""),
+            ("CMAKE_BUILD_TYPE", "Release"),
+        ]);
+        assert!(configs.single_config);
+        assert_eq!(configs.default_config().as_deref(), Some("Release"));
+        assert_eq!(configs.canonical("RELEASE").unwrap(), "Release");
+        assert_eq!(
+            configs.canonical("Debug").unwrap_err().to_string(),
+            "The build dir was configured for 'Release' only (CMAKE_BUILD_TYPE), not for 'Debug'."
+        );
+
+        // without CMAKE_BUILD_TYPE, any config is taken as given
+        let configs = cmake_configs(&[]);
+        assert!(configs.single_config);
+        assert_eq!(configs.default_config(), None);
+        assert_eq!(configs.canonical("debug").unwrap(), "debug");
+    }
+}
@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// One `--gtest_list_tests` call.
+#[derive(Debug, Clone)]
+pub struct GtestRequest {
+    pub exe: String,
+    pub args: Vec<String>,
+    pub cwd: String,
+    pub env: HashMap<String, String>,
+}
+
+/// Identifies a build of an executable, so that rebuilt executables are discovered again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheKey {
+    exe: String,
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    args: Vec<String>,
+}
+impl CacheKey {
+    fn from(request: &GtestRequest) -> Option<CacheKey> {
+        let metadata = std::fs::metadata(&request.exe).ok()?;
+        let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(CacheKey {
+            exe: request.exe.clone(),
+            size: metadata.len(),
+            mtime_secs: mtime.as_secs(),
+            mtime_nanos: mtime.subsec_nanos(),
+            args: request.args.clone(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    key: CacheKey,
+    tests: Vec<String>,
+}
+
+/// Test lists of gtest executables, cached in the build dir across invocations.
+pub struct GtestDiscovery {
+    path: PathBuf,
+    entries: Vec<CacheEntry>,
+}
+impl GtestDiscovery {
+    /// Loads the cache, or starts with an empty one if `refresh` is set.
+    pub fn load(build_dir: &Path, refresh: bool) -> GtestDiscovery {
+        let path = build_dir.join(".mwtest").join("gtest_discovery.json");
+        let entries = if refresh {
+            vec![]
+        } else {
+            std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| serde_json::from_str(&content).ok())
+                .unwrap_or_default()
+        };
+        GtestDiscovery { path, entries }
+    }
+
+    /// Runs all requests that are not cached yet in parallel and updates the cache file.
+    pub fn discover(&mut self, requests: &[GtestRequest]) {
+        let mut missing: Vec<&GtestRequest> = vec![];
+        for request in requests {
+            let is_duplicate = missing
+                .iter()
+                .any(|m| m.exe == request.exe && m.args == request.args);
+            if !is_duplicate && self.get(request).is_none() {
+                missing.push(request);
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        for request in &missing {
+            if !PathBuf::from(&request.exe).exists() {
+                println!(
+                    "Could not find GTest executable at {}!\nDid you forget to build?",
+                    request.exe
+                );
+                std::process::exit(-1);
+            }
+        }
+
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(vec![]);
+        let n_workers = num_cpus::get().min(missing.len());
+        std::thread::scope(|scope| {
+            for _ in 0..n_workers {
+                scope.spawn(|| {
+                    while let Some(request) = missing.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        let result = list_tests(request);
+                        results.lock().unwrap().push((*request, result));
+                    }
+                });
+            }
+        });
+
+        for (request, result) in results.into_inner().unwrap() {
+            match result {
+                Ok(tests) => {
+                    if let Some(key) = CacheKey::from(request) {
+                        self.entries
+                            .retain(|e| e.key.exe != key.exe || e.key.args != key.args);
+                        self.entries.push(CacheEntry { key, tests });
+                    }
+                }
+                Err(message) => {
+                    println!("{}", message);
+                    std::process::exit(-1);
+                }
+            }
+        }
+        self.save();
+    }
+
+    /// Returns the cached tests, or lists them now if they couldn't be cached.
+    pub fn tests(&self, request: &GtestRequest) -> Vec<String> {
+        match self.get(request) {
+            Some(tests) => tests.to_vec(),
+            None => list_tests(request).unwrap_or_else(|message| {
+                println!("{}", message);
+                std::process::exit(-1);
+            }),
+        }
+    }
+
+    fn get(&self, request: &GtestRequest) -> Option<&[String]> {
+        let key = CacheKey::from(request)?;
+        self.entries
+            .iter()
+            .find(|e| e.key == key)
+            .map(|e| &e.tests[..])
+    }
+
+    fn save(&self) {
+        // the cache is only an optimization, e.g. the build dir may be read-only
+        let _ = std::fs::create_dir_all(self.path.parent().unwrap());
+        if let Ok(content) = serde_json::to_string(&self.entries) {
+            let _ = std::fs::write(&self.path, content);
+        }
+    }
+}
+
+fn list_tests(request: &GtestRequest) -> Result<Vec<String>, String> {
+    let output = std::process::Command::new(&request.exe)
+        .arg("--gtest_list_tests")
+        .args(&request.args)
+        .current_dir(&request.cwd)
+        .envs(&request.env)
+        .output()
+        .map_err(|e| format!("Failed to execute {}: {}", request.exe, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "Failed to execute {} {:?}: {:?}",
+            request.exe, request.args, output
+        ));
+    }
+    let output = std::str::from_utf8(&output.stdout)
+        .map_err(|_| "could not decode find_gtest output as utf-8!".to_string())?;
+    Ok(parse_test_list(output))
+}
+
+fn parse_test_list(output: &str) -> Vec<String> {
+    let mut group = String::new();
+    let mut results = Vec::new();
+    for line in output
+        .lines()
+        .filter(|l| !l.contains("DISABLED"))
+        .map(|l| l.split('#').next().unwrap())
+    {
+        if !line.starts_with(' ') {
+            group = line.trim().to_string();
+        } else {
+            results.push(group.clone() + line.trim());
+        }
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gtest_list() {
+        let output = "Running main() from gtest_main.cc\n\
+                      Sample.\n  Succeed\n  DISABLED_Skip\n\
+                      Typed/0.  # TypeParam = int\n  Works\n";
+        assert_eq!(parse_test_list(output), ["Sample.Succeed", "Typed/0.Works"]);
+    }
+
+    #[test]
+    fn cache_is_keyed_by_exe_and_args() {
+        let exe = std::env::current_exe()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let request = GtestRequest {
+            exe: exe.clone(),
+            args: vec!["--gtest_filter=Sample.*".into()],
+            cwd: ".".into(),
+            env: HashMap::new(),
+        };
+        let mut discovery = GtestDiscovery {
+            path: PathBuf::new(),
+            entries: vec![CacheEntry {
+                key: CacheKey::from(&request).unwrap(),
+                tests: vec!["Sample.Succeed".into()],
+            }],
+        };
+        assert_eq!(discovery.get(&request).unwrap(), ["Sample.Succeed"]);
+
+        let other_args = GtestRequest {
+            args: vec!["--gtest_filter=*".into()],
+            ..request.clone()
+        };
+        assert!(discovery.get(&other_args).is_none());
+
+        // a rebuilt executable has another size or mtime
+        discovery.entries[0].key.size += 1;
+        assert!(discovery.get(&request).is_none());
+    }
+}
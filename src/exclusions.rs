@@ -0,0 +1,183 @@
+use crate::util::split_quoted;
+use chrono::NaiveDate;
+use simple_eyre::eyre::{eyre, ContextCompat, Result, WrapErr};
+use std::path::Path;
+
+/// A test that must not be run, as listed in an exclusion file:
+///
+/// `machsim --id "some/test" --reason "crashes on CI" --ticket MW-1234 --expires 2021-06-30`
+///
+/// Everything after the id is optional. Lines that begin with '#' are comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exclusion {
+    pub app: String,
+    pub id: String,
+    pub reason: Option<String>,
+    pub ticket: Option<String>,
+    pub expires: Option<NaiveDate>,
+}
+impl Exclusion {
+    /// Human readable summary of why (and until when) the test is excluded.
+    pub fn description(&self) -> String {
+        let mut details = vec![];
+        if let Some(ticket) = &self.ticket {
+            details.push(ticket.clone());
+        }
+        if let Some(expires) = &self.expires {
+            details.push(format!("until {}", expires));
+        }
+        let reason = self.reason.as_deref().unwrap_or("excluded");
+        if details.is_empty() {
+            reason.to_string()
+        } else {
+            format!("{} ({})", reason, details.join(", "))
+        }
+    }
+}
+
+pub struct Exclusions(Vec<Exclusion>);
+impl Exclusions {
+    pub fn load(path: &Path) -> Result<Exclusions> {
+        let content = std::fs::read_to_string(path)?;
+        Exclusions::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Exclusions> {
+        let mut exclusions = vec![];
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let exclusion = parse_line(line).wrap_err_with(|| format!("line {}", i + 1))?;
+            exclusions.push(exclusion);
+        }
+        Ok(Exclusions(exclusions))
+    }
+
+    /// Drops all entries that expired before the given date and returns them.
+    pub fn remove_expired(&mut self, today: NaiveDate) -> Vec<Exclusion> {
+        let (expired, active) = self
+            .0
+            .drain(..)
+            .partition(|e| matches!(e.expires, Some(d) if d < today));
+        self.0 = active;
+        expired
+    }
+
+    pub fn find(&self, app: &str, id: &str) -> Option<&Exclusion> {
+        let normalize = |input: &str| input.to_lowercase().replace('\\', "/");
+        let (app, id) = (normalize(app), normalize(id));
+        self.0
+            .iter()
+            .find(|e| normalize(&e.app) == app && normalize(&e.id) == id)
+    }
+}
+
+fn parse_line(line: &str) -> Result<Exclusion> {
+    let tokens = split_quoted(line)?;
+    let mut tokens = tokens.into_iter();
+    let app = tokens.next().wrap_err("missing app name")?;
+    let mut exclusion = Exclusion {
+        app,
+        id: String::new(),
+        reason: None,
+        ticket: None,
+        expires: None,
+    };
+    while let Some(key) = tokens.next() {
+        let value = tokens
+            .next()
+            .wrap_err_with(|| format!("missing value after {}", key))?;
+        match key.as_str() {
+            "--id" => exclusion.id = value,
+            "--reason" => exclusion.reason = Some(value),
+            "--ticket" => exclusion.ticket = Some(value),
+            "--expires" => {
+                let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                    .wrap_err_with(|| format!("invalid date '{}' (expected YYYY-MM-DD)", value))?;
+                exclusion.expires = Some(date);
+            }
+            _ => return Err(eyre!("unknown option '{}'", key)),
+        }
+    }
+    if exclusion.id.is_empty() {
+        return Err(eyre!("missing --id"));
+    }
+    Ok(exclusion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entries() {
+        let exclusions = Exclusions::parse(
+            r#"
+            # flaky
+            machsim --id "BinTestInputPools/a b"
+            cadcam --id Drill.Test --reason "crashes on CI" --ticket MW-1 --expires 2021-06-30
+            "#,
+        )
+        .unwrap();
+        assert_eq!(exclusions.0.len(), 2);
+        assert_eq!(exclusions.0[0].id, "BinTestInputPools/a b");
+        assert_eq!(exclusions.0[0].description(), "excluded");
+        assert_eq!(
+            exclusions.0[1].description(),
+            "crashes on CI (MW-1, until 2021-06-30)"
+        );
+    }
+
+    #[test]
+    fn parse_list_output() {
+        // lines copied from "mwtest list", one of them with a reason appended
+        let exclusions = Exclusions::parse(
+            "machsim --id \"BinTestInputPools/with space/a.xml\"\n\
+             gtests --id \"Typed/0.Works [int]\" --reason \"hangs\"",
+        )
+        .unwrap();
+        assert!(exclusions
+            .find("machsim", "BinTestInputPools/with space/a.xml")
+            .is_some());
+        let gtest = exclusions.find("gtests", "Typed/0.Works [int]").unwrap();
+        assert_eq!(gtest.reason.as_deref(), Some("hangs"));
+    }
+
+    #[test]
+    fn matches_per_app() {
+        let exclusions = Exclusions::parse("machsim --id BinTestInputPools\\a").unwrap();
+        assert!(exclusions.find("MachSim", "bintestinputpools/a").is_some());
+        assert!(exclusions
+            .find("machsimCnc", "BinTestInputPools/a")
+            .is_none());
+    }
+
+    #[test]
+    fn remove_expired() {
+        let mut exclusions = Exclusions::parse(
+            "a --id x --expires 2021-06-30\n\
+             b --id y --expires 2021-07-01\n\
+             c --id z",
+        )
+        .unwrap();
+        let expired = exclusions.remove_expired(NaiveDate::from_ymd_opt(2021, 7, 1).unwrap());
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].app, "a");
+        assert_eq!(exclusions.0.len(), 2);
+    }
+
+    #[test]
+    fn invalid_lines() {
+        for line in &[
+            "machsim",
+            "machsim --id",
+            "machsim --id x --expires tomorrow",
+            "machsim --id x --owner me",
+            "machsim --id \"x",
+        ] {
+            assert!(Exclusions::parse(line).is_err(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Wall times of earlier runs per app and test id, cached in the build dir, so that the longest
+/// tests can be started first.
+pub struct DurationHistory {
+    path: PathBuf,
+    /// seconds
+    durations: HashMap<String, HashMap<String, f32>>,
+}
+impl DurationHistory {
+    pub fn load(build_dir: &Path) -> DurationHistory {
+        let path = build_dir.join(".mwtest").join("durations.json");
+        let durations = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        DurationHistory { path, durations }
+    }
+
+    /// The expected duration in seconds, if the test was run before.
+    pub fn estimate(&self, app_name: &str, test_id: &str) -> Option<f32> {
+        self.durations.get(app_name)?.get(test_id).copied()
+    }
+
+    /// Averages with the previous duration, so that a single outlier doesn't reorder everything.
+    pub fn record(&mut self, app_name: &str, test_id: &str, duration: Duration) {
+        let seconds = duration.as_secs_f32();
+        let entry = self
+            .durations
+            .entry(app_name.to_string())
+            .or_default()
+            .entry(test_id.to_string())
+            .or_insert(seconds);
+        *entry = (*entry + seconds) / 2.0;
+    }
+
+    pub fn save(&self) {
+        // the history is only an optimization, e.g. the build dir may be read-only
+        let _ = std::fs::create_dir_all(self.path.parent().unwrap());
+        if let Ok(content) = serde_json::to_string(&self.durations) {
+            let _ = std::fs::write(&self.path, content);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_estimate() {
+        let mut history = DurationHistory {
+            path: PathBuf::new(),
+            durations: HashMap::new(),
+        };
+        assert_eq!(history.estimate("app", "a"), None);
+        history.record("app", "a", Duration::from_secs(10));
+        assert_eq!(history.estimate("app", "a"), Some(10.0));
+        history.record("app", "a", Duration::from_secs(20));
+        assert_eq!(history.estimate("app", "a"), Some(15.0));
+        assert_eq!(history.estimate("other", "a"), None);
+    }
+
+    #[test]
+    fn load_saved() {
+        let build_dir = std::env::temp_dir().join(format!("mwtest_history_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&build_dir);
+        let mut history = DurationHistory::load(&build_dir);
+        assert_eq!(history.estimate("app", "a"), None);
+        history.record("app", "a", Duration::from_millis(1500));
+        history.save();
+
+        let history = DurationHistory::load(&build_dir);
+        assert_eq!(history.estimate("app", "a"), Some(1.5));
+        std::fs::remove_dir_all(&build_dir).unwrap();
+        // a missing or broken file is an empty history
+        std::fs::create_dir_all(build_dir.join(".mwtest")).unwrap();
+        std::fs::write(build_dir.join(".mwtest").join("durations.json"), "{").unwrap();
+        assert_eq!(DurationHistory::load(&build_dir).estimate("app", "a"), None);
+        std::fs::remove_dir_all(&build_dir).unwrap();
+    }
+}
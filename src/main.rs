@@ -1,8 +1,16 @@
 mod config;
+mod discovery;
+mod exclusions;
+mod history;
+mod merge;
 mod report;
 mod runnable;
 mod scheduler;
+mod selection;
+mod shard;
 mod svn;
+mod util;
+mod validate;
 
 use simple_eyre::eyre::{eyre, ContextCompat, Result, WrapErr};
 use std::collections::HashMap;
@@ -13,6 +21,9 @@ use structopt::StructOpt;
 #[global_allocator]
 static GLOBAL: std::alloc::System = std::alloc::System;
 
+/// Defaults for the options below can be set in a ".mwtest.toml" (searched from the current
+/// directory upwards) or in "mwtest/config.toml" in the user's config directory. Keys are
+/// dev_dir, build_dir, testcases_dir, build_type, preset, config and debugger.
 #[derive(StructOpt)]
 #[structopt(name = "mwtest", rename_all = "kebab-case")]
 struct Args {
@@ -35,7 +46,7 @@ struct Args {
     #[structopt(long)]
     build_type: Option<String>,
 
-    /// specifies which tests to run (like ci, nightly)
+    /// specifies which tests to run (like ci, nightly); combine with + and remove with - (like nightly-ci)
     #[structopt(long)]
     preset: Option<String>,
 
@@ -50,6 +61,7 @@ struct Args {
 #[derive(StructOpt)]
 enum SubCommands {
     Build {
+        /// app names, aliases, tags or expressions like 'tag:machsimtests & !app:machsimPerformance'
         app_names: Vec<String>,
 
         /// Don't show the XGE monitor for builds.
@@ -57,17 +69,68 @@ enum SubCommands {
         no_monitor: bool,
     },
     List {
+        /// app names, aliases, tags or expressions like 'tag:machsimtests & !app:machsimPerformance'
         app_names: Vec<String>,
 
         /// Show only ids that contain one of the given substrings.
         #[structopt(short, long)]
         filter: Vec<String>,
+
+        /// Show only ids that match one of the given regular expressions.
+        #[structopt(long)]
+        filter_regex: Vec<String>,
+
+        /// Show only these ids. Globs like 'smoke/**/drill*' (with '*', '**' and '?') are allowed.
+        #[structopt(long)]
+        id: Vec<String>,
+
+        /// Hide ids that contain one of the given substrings.
+        #[structopt(long)]
+        exclude_filter: Vec<String>,
+
+        /// Hide the tests that are named in this exclusion file (see "mwtest run --help").
+        #[structopt(long)]
+        exclusion_file: Option<String>,
+
+        /// List the tests of gtest executables again, even if they haven't changed.
+        #[structopt(long)]
+        refresh_discovery: bool,
+
+        /// Show only the tests of one part (like 2/4) of the selected tests.
+        #[structopt(long)]
+        shard: Option<shard::Shard>,
     },
     Run(RunArgs),
     Info {
         app_name: String,
     },
+    /// Print the command line of a test, ready to be pasted into a shell. Arguments after "--" are
+    /// passed on like for "mwtest run".
+    ShowCommand {
+        app_name: String,
+
+        /// test id. Globs like 'smoke/**/drill*' (with '*', '**' and '?') are allowed.
+        #[structopt(long)]
+        id: String,
+
+        #[structopt(skip)]
+        extra_args: Vec<String>,
+    },
+    /// Run one test under a debugger: $MWTEST_DEBUGGER, "debugger" in the defaults files or gdb.
+    /// Arguments after "--" are passed on like for "mwtest run". The wrapper of the group is left
+    /// out, and generated outputs go to the --output-dir or a "mwtest-debug" temp folder.
+    Debug {
+        app_name: String,
+
+        /// test id
+        #[structopt(long)]
+        id: String,
+
+        #[structopt(skip)]
+        extra_args: Vec<String>,
+    },
     Checkout {
+        /// app names, aliases, tags or expressions like 'tag:machsimtests & !app:machsimPerformance'
         app_names: Vec<String>,
 
         /// will convert the testcases folder to a sparse checkout
@@ -99,25 +162,65 @@ enum SubCommands {
         branch: Option<String>,
     },
     Update {
+        /// app names, aliases, tags or expressions like 'tag:machsimtests & !app:machsimPerformance'
         app_names: Vec<String>,
     },
+    /// Check apps.json for unknown keys, missing test generators and invalid values.
+    ValidateConfig {
+        /// apps.json or a folder with apps.json and/or apps.d (default: the one mwtest would load)
+        path: Option<String>,
+    },
+    /// Combine the results of several runs (like the shards of one run) into one output dir, with
+    /// a single results.xml, the artifacts and the logs of all of them.
+    MergeResults {
+        /// folder for the combined results. It is replaced if it contains a results.xml.
+        out_dir: String,
+        /// output dirs of "mwtest run"
+        #[structopt(required = true)]
+        in_dirs: Vec<String>,
+    },
 }
 
 #[derive(StructOpt)]
 pub struct RunArgs {
+    /// app names, aliases, tags or expressions like 'tag:machsimtests & !app:machsimPerformance'
     app_names: Vec<String>,
 
-    #[structopt(long, conflicts_with = "filter")]
+    /// Run only these ids. Globs like 'smoke/**/drill*' (with '*', '**' and '?') are allowed.
+    #[structopt(long)]
     id: Vec<String>,
 
     /// Run only ids that contain one of the given substrings.
     #[structopt(short, long)]
     filter: Vec<String>,
 
+    /// Run only ids that match one of the given regular expressions.
+    #[structopt(long)]
+    filter_regex: Vec<String>,
+
+    /// Skip ids that contain one of the given substrings, even if they are selected by --filter,
+    /// --filter-regex or --id.
+    #[structopt(long)]
+    exclude_filter: Vec<String>,
+
+    /// List the tests of gtest executables again, even if they haven't changed.
+    #[structopt(long)]
+    refresh_discovery: bool,
+
+    /// Run only one part (like 2/4) of the selected tests, e.g. to split a run across CI agents.
+    /// Tests are assigned by a hash of app and id.
+    #[structopt(long)]
+    shard: Option<shard::Shard>,
+
     /// Show the full test output, even for succeeded tests.
     #[structopt(short, long)]
     verbose: bool,
 
+    /// Print the commands that would be run (with cwd, timeout and execution style) instead of
+    /// running them.
+    #[structopt(long)]
+    dry_run: bool,
+
     /// Run with multiple local threads. You can also give the thread count explicitly.
     #[structopt(short, long, conflicts_with = "xge")]
     parallel: Option<Option<usize>>,
@@ -156,8 +259,26 @@ pub struct RunArgs {
     #[structopt(long, default_value = "1")]
     timeout_factor: f32,
 
-    /// Test ids named in this file are never run. The format is the same that is printed by "mwtest list".
-    /// Lines that begin with '#' are comments.
+    /// Prepend this to every test command, like "valgrind --error-exitcode=99 --". Replaces the
+    /// "wrapper" of the groups in apps.json. Files that the wrapper writes to {{wrapper_log_dir}}
+    /// are kept as artifacts.
+    #[structopt(long, parse(try_from_str = parse_wrapper))]
+    wrapper: Option<config::CommandTemplate>,
+
+    /// Multiply the timeouts of tests that run with a wrapper by this factor (in addition to
+    /// --timeout-factor). Replaces the "wrapper_timeout_factor" of the groups in apps.json.
+    #[structopt(long)]
+    wrapper_timeout_factor: Option<f32>,
+
+    /// Capacity of a resource that groups hold while their tests run, like "license=2". Can also
+    /// be set in the [resources] table of the defaults files. Unlisted resources have a capacity
+    /// of 1. Only local runs respect resources and "max_parallel".
+    #[structopt(long = "resource", parse(try_from_str = parse_resource))]
+    resources: Vec<(String, usize)>,
+
+    /// Test ids named in this file are never run and reported as skipped. The format is the same
+    /// that is printed by "mwtest list", optionally followed by --reason "...", --ticket ... and
+    /// --expires YYYY-MM-DD. Expired entries are run again. Lines that begin with '#' are comments.
     #[structopt(long)]
     exclusion_file: Option<String>,
 
@@ -166,13 +287,37 @@ pub struct RunArgs {
     /// where the changed path has a 1-to-1 mapping with the test (like exactoutput).
     #[structopt(long)]
     run_only_changed_file: Option<String>,
+
+    /// Arguments after "--" (like "mwtest run app -- --gtest_break_on_failure"), which are
+    /// inserted at {{extra_args}} or appended to every test command.
+    #[structopt(skip)]
+    extra_args: Vec<String>,
+
+    /// Runs the test commands without any wrapper, for "mwtest debug".
+    #[structopt(skip)]
+    no_wrapper: bool,
+}
+
+fn parse_wrapper(wrapper: &str) -> Result<config::CommandTemplate> {
+    Ok(config::CommandTemplate(util::split_quoted(wrapper)?))
+}
+
+fn parse_resource(resource: &str) -> Result<(String, usize)> {
+    let invalid = || eyre!("invalid resource '{}' (expected NAME=CAPACITY)", resource);
+    let (name, capacity) = resource.split_once('=').ok_or_else(invalid)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(invalid());
+    }
+    let capacity = capacity.trim().parse().map_err(|_| invalid())?;
+    Ok((name.to_string(), capacity))
 }
 
 fn main() -> Result<()> {
     simple_eyre::install()?;
 
     let mut args = vec![];
-    let mut extra_args = vec![]; // TODO: handle extra args
+    let mut extra_args = vec![];
     let mut in_extra_args = false;
     for arg in std::env::args() {
         if arg == "--" {
@@ -183,7 +328,33 @@ fn main() -> Result<()> {
             args.push(arg);
         }
     }
-    let args = Args::from_iter(args);
+    let mut args = Args::from_iter(args);
+    match &mut args.cmd {
+        SubCommands::Run(run_args) => run_args.extra_args = extra_args,
+        SubCommands::ShowCommand {
+            extra_args: args, ..
+        }
+        | SubCommands::Debug {
+            extra_args: args, ..
+        } => *args = extra_args,
+        _ if !extra_args.is_empty() => {
+            println!("Arguments after '--' are only supported by 'run', 'show-command' and 'debug'.");
+            std::process::exit(-1);
+        }
+        _ => {}
+    }
+
+    // an explicitly given apps.json can be checked without any build or dev folder
+    if let SubCommands::ValidateConfig { path: Some(path) } = &args.cmd {
+        return cmd_validate_config(Path::new(path));
+    }
+    if let SubCommands::MergeResults { out_dir, in_dirs } = &args.cmd {
+        let in_dirs: Vec<PathBuf> = in_dirs.iter().map(PathBuf::from).collect();
+        if !merge::merge_results(Path::new(out_dir), &in_dirs)? {
+            std::process::exit(-1)
+        }
+        return Ok(());
+    }
 
     let input_paths = config::InputPaths::from(
         args.dev_dir,
@@ -194,6 +365,11 @@ fn main() -> Result<()> {
         args.config,
     )?;
 
+    if let SubCommands::ValidateConfig { path: None } = &args.cmd {
+        let config_dir = config::AppsConfig::find(&input_paths.dev_dir, &input_paths.build_dir)?;
+        return cmd_validate_config(&config_dir);
+    }
+
     let apps_config = config::AppsConfig::load(&input_paths.dev_dir, &input_paths.build_dir)
         .wrap_err("Failed to load apps.json!")?;
 
@@ -202,37 +378,62 @@ fn main() -> Result<()> {
             app_names,
             no_monitor,
         } => {
-            let apps = apps_config.select_build_and_preset(&app_names, &input_paths)?;
+            let apps = apps_config.select_build(&app_names, &input_paths)?;
             cmd_build(&apps, &input_paths, no_monitor)?;
         }
-        SubCommands::List { app_names, filter } => {
+        SubCommands::List {
+            app_names,
+            filter,
+            filter_regex,
+            id,
+            exclude_filter,
+            exclusion_file,
+            refresh_discovery,
+            shard,
+        } => {
             if !app_names.is_empty() {
                 let apps = apps_config.select_build_and_preset(&app_names, &input_paths)?;
                 let filter_args = FilterArgs {
                     filter: &filter,
-                    ids: &[],
-                    exclusion_file: &None, // TODO
+                    filter_regex: &filter_regex,
+                    ids: &id,
+                    exclude_filter: &exclude_filter,
+                    exclusion_file: &exclusion_file,
+                    refresh_discovery,
+                    shard,
                 };
-                let app_tests = generate_app_tests(&filter_args, &input_paths, &apps, false);
+                let app_tests = generate_app_tests(&filter_args, &input_paths, &apps, false)?;
                 cmd_list_tests(&app_tests);
             } else {
                 cmd_list_apps(&apps_config);
             }
         }
-        SubCommands::Run(run_args) => {
+        SubCommands::Run(mut run_args) => {
+            // the command line overrides the defaults files
+            let mut resources: Vec<_> = config::resource_capacities()?.into_iter().collect();
+            resources.append(&mut run_args.resources);
+            run_args.resources = resources;
             let apps = apps_config.select_build_and_preset(&run_args.app_names, &input_paths)?;
             let can_run_raw_gtest = run_args.filter.is_empty()
+                && run_args.filter_regex.is_empty()
                 && run_args.id.is_empty()
+                && run_args.exclude_filter.is_empty()
+                && run_args.exclusion_file.is_none()
+                && run_args.shard.is_none()
                 && run_args.parallel.is_none()
                 && !run_args.xge
                 && run_args.repeat_if_failed == 0;
             let filter_args = FilterArgs {
                 filter: &run_args.filter,
+                filter_regex: &run_args.filter_regex,
                 ids: &run_args.id,
+                exclude_filter: &run_args.exclude_filter,
                 exclusion_file: &run_args.exclusion_file,
+                refresh_discovery: run_args.refresh_discovery,
+                shard: run_args.shard,
             };
             let app_tests =
-                generate_app_tests(&filter_args, &input_paths, &apps, can_run_raw_gtest);
+                generate_app_tests(&filter_args, &input_paths, &apps, can_run_raw_gtest)?;
             let out_dir = args
                 .output_dir
                 .map(PathBuf::from)
@@ -241,6 +442,10 @@ fn main() -> Result<()> {
                 out_dir: out_dir.clone(),
                 tmp_dir: out_dir.join("tmp"),
             };
+            if run_args.dry_run {
+                cmd_dry_run(&input_paths, &app_tests, &output_paths, &run_args);
+                return Ok(());
+            }
             let success = cmd_run(&input_paths, &app_tests, &output_paths, &run_args)?
                 || run_args.treat_completion_as_success;
             if !success {
@@ -250,6 +455,69 @@ fn main() -> Result<()> {
         SubCommands::Info { app_name } => {
             cmd_info(app_name, &apps_config, &input_paths)?;
         }
+        SubCommands::ShowCommand {
+            app_name,
+            id,
+            extra_args,
+        } => {
+            // generated outputs go to a folder that isn't reset by the next "mwtest run"
+            let tmp_dir = std::env::temp_dir().join("mwtest");
+            let output_paths = OutputPaths {
+                out_dir: tmp_dir.clone(),
+                tmp_dir,
+            };
+            let mut run_args = single_test_args(&id, extra_args);
+            // the folders for generated outputs are named but not created
+            run_args.dry_run = true;
+            let instances = instantiate_tests(
+                apps_config,
+                &input_paths,
+                &output_paths,
+                app_name,
+                &run_args,
+            )?;
+            // a glob can match several tests
+            let print_ids = instances.len() > 1;
+            for instance in instances {
+                if print_ids {
+                    println!("# {}", instance.test_id.id);
+                }
+                println!("{}", instance.command.shell_line());
+            }
+        }
+        SubCommands::Debug {
+            app_name,
+            id,
+            extra_args,
+        } => {
+            // not test_output: "mwtest run" refuses to reset a folder without results.xml
+            let out_dir = args
+                .output_dir
+                .map(PathBuf::from)
+                .unwrap_or_else(|| std::env::temp_dir().join("mwtest-debug"));
+            let output_paths = OutputPaths {
+                out_dir: out_dir.clone(),
+                tmp_dir: out_dir.join("tmp"),
+            };
+            let mut run_args = single_test_args(&id, extra_args);
+            // the debugger runs the test itself, not the wrapper (like valgrind) of its group
+            run_args.no_wrapper = true;
+            let mut instances = instantiate_tests(
+                apps_config,
+                &input_paths,
+                &output_paths,
+                app_name,
+                &run_args,
+            )?;
+            if instances.len() > 1 {
+                return Err(eyre!(
+                    "--id \"{}\" matches {} tests, but only one can be debugged.",
+                    id,
+                    instances.len()
+                ));
+            }
+            cmd_debug(instances.remove(0), &input_paths, &output_paths)?;
+        }
         SubCommands::Checkout {
             app_names,
             force,
@@ -265,7 +533,8 @@ fn main() -> Result<()> {
             for app in apps.0.values() {
                 for test in &app.tests {
                     for group in &test.groups {
-                        if let Some(expr) = &group.find_glob {
+                        let path_glob = group.find_glob.as_ref().or(group.find_parent_dir.as_ref());
+                        if let Some(expr) = path_glob {
                             if id.is_empty() {
                                 // check out all test files
                                 let path = expr.split('*').next().unwrap();
@@ -276,9 +545,10 @@ fn main() -> Result<()> {
                                     let mut path = test
                                         .id_pattern
                                         .as_ref()
-                                        .map(|p| p.replace("(.*)", &id))
+                                        .map(|p| p.replace("(.*)", id))
                                         .unwrap_or_else(|| id.to_string());
-                                    if app.checkout_parent {
+                                    // ids of find_parent_dir groups already name the test directory
+                                    if app.checkout_parent && group.find_parent_dir.is_none() {
                                         path = relative_path::RelativePath::new(&path)
                                             .parent()
                                             .unwrap()
@@ -324,7 +594,7 @@ fn main() -> Result<()> {
                 }
                 _ => {
                     svn::checkout(
-                        &dev_dir,
+                        dev_dir,
                         &input_paths.testcases_dir,
                         &paths,
                         force,
@@ -340,7 +610,8 @@ fn main() -> Result<()> {
             for app in apps.0.values() {
                 for test in &app.tests {
                     for group in &test.groups {
-                        if let Some(expr) = &group.find_glob {
+                        let path_glob = group.find_glob.as_ref().or(group.find_parent_dir.as_ref());
+                        if let Some(expr) = path_glob {
                             paths.push(expr.split('*').next().unwrap().to_string());
                         }
                         for path in &group.testcases_dependencies {
@@ -356,12 +627,13 @@ fn main() -> Result<()> {
                 std::process::exit(-1)
             }
             svn::update(
-                &input_paths.dev_dir.as_ref().unwrap(),
+                input_paths.dev_dir.as_ref().unwrap(),
                 &input_paths.testcases_dir,
                 &paths,
                 true,
             )?;
         }
+        SubCommands::ValidateConfig { .. } | SubCommands::MergeResults { .. } => unreachable!(),
     }
 
     Ok(())
@@ -383,13 +655,13 @@ fn cmd_build(apps: &config::Apps, paths: &config::InputPaths, no_monitor: bool)
             .project
             .as_ref()
             .wrap_err_with(|| format!("no project defined for {}", name))?;
-        let deps = dependencies.entry(solution).or_insert_with(Vec::new);
+        let deps = dependencies.entry(solution).or_default();
         (*deps).push(project);
     }
-    let has_buildconsole = match Command::new("buildConsole").output() {
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
-        _ => true,
-    };
+    let has_buildconsole = !matches!(
+        Command::new("buildConsole").output(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound
+    );
     for (solution, projects) in dependencies {
         let projects = projects.join(",");
         let status = if has_buildconsole {
@@ -429,6 +701,30 @@ fn cmd_build(apps: &config::Apps, paths: &config::InputPaths, no_monitor: bool)
     Ok(())
 }
 
+fn cmd_validate_config(path: &Path) -> Result<()> {
+    let problems = if path.is_dir() {
+        validate::validate_dir(path)
+    } else {
+        validate::validate_file(path)
+            .map(|problems| problems.into_iter().map(|p| (path.into(), p)).collect())
+    }
+    .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    for (file, problem) in &problems {
+        println!(
+            "{}: {}: {}",
+            file.display(),
+            problem.location,
+            problem.message
+        );
+    }
+    if problems.is_empty() {
+        println!("{} is valid.", path.display());
+        Ok(())
+    } else {
+        Err(eyre!("found {} problem(s) in apps.json", problems.len()))
+    }
+}
+
 fn cmd_list_apps(apps: &config::AppsConfig) {
     for name in apps.app_names() {
         println!("  {}", name);
@@ -439,7 +735,7 @@ fn cmd_list_tests(apps: &[AppWithTests]) {
     for app in apps {
         for group in &app.tests {
             for test_id in &group.test_ids {
-                println!("{} --id {}", app.name, test_id.id);
+                println!("{} --id \"{}\"", app.name, test_id.id);
             }
         }
     }
@@ -468,12 +764,151 @@ fn cmd_run(
         output_paths.out_dir.to_str().unwrap()
     );
 
-    let tests = runnable::create_run_commands(&input_paths, &test_apps, &output_paths, &run_args);
-    if tests.is_empty() {
+    let tests = runnable::create_run_commands(input_paths, test_apps, output_paths, run_args);
+    let skipped: Vec<_> = test_apps
+        .iter()
+        .flat_map(|app| {
+            app.skipped.iter().map(move |(test_id, reason)| {
+                (app.name.as_str(), test_id.id.as_str(), reason.as_str())
+            })
+        })
+        .collect();
+    if tests.is_empty() && skipped.is_empty() {
         println!("WARNING: No tests were selected.");
         std::process::exit(0); // counts as success
     }
-    scheduler::run(&input_paths, tests, &output_paths, &run_args)
+    scheduler::run(input_paths, tests, &skipped, output_paths, run_args)
+}
+
+fn cmd_dry_run(
+    input_paths: &config::InputPaths,
+    test_apps: &[AppWithTests],
+    output_paths: &OutputPaths,
+    run_args: &crate::RunArgs,
+) {
+    let tests = runnable::create_run_commands(input_paths, test_apps, output_paths, run_args);
+    for group in &tests {
+        let timeout = match group.timeout {
+            Some(timeout) => format!("{}s", timeout),
+            None => "none".to_string(),
+        };
+        for creator in group.gtest_generator.iter().chain(&group.tests) {
+            let command = creator.instantiate().command;
+            if creator.is_g_multitest {
+                println!("{} (all tests in one gtest call)", group.app_name);
+            } else {
+                println!("{} --id \"{}\"", group.app_name, creator.test_id.id);
+            }
+            println!("  command: {:?}", command.command);
+            println!("  cwd: {}", command.cwd);
+            if !command.env.is_empty() {
+                println!("  environment: {:?}", command.env);
+            }
+            println!("  timeout: {}", timeout);
+            println!("  execution style: {}", group.execution_style);
+            if let Some(tmp_path) = &command.tmp_path {
+                println!("  generated output: {}", tmp_path.display());
+            }
+            if let Some(log_dir) = &command.wrapper_log_dir {
+                println!("  wrapper logs: {}", log_dir.display());
+            }
+        }
+    }
+    for app in test_apps {
+        for (test_id, reason) in &app.skipped {
+            println!("{} --id \"{}\"", app.name, test_id.id);
+            println!("  skipped: {}", reason);
+        }
+    }
+}
+
+/// The defaults of "mwtest run" for the tests that match `id`.
+fn single_test_args(id: &str, extra_args: Vec<String>) -> RunArgs {
+    let mut run_args = RunArgs::from_iter(&["mwtest", &format!("--id={}", id)]);
+    run_args.extra_args = extra_args;
+    run_args
+}
+
+/// The commands of the tests of one app that match the --id of `run_args`.
+fn instantiate_tests(
+    apps_config: config::AppsConfig,
+    input_paths: &config::InputPaths,
+    output_paths: &OutputPaths,
+    app_name: String,
+    run_args: &RunArgs,
+) -> Result<Vec<runnable::TestInstance>> {
+    let apps = apps_config.select_build_and_preset(&[app_name], input_paths)?;
+    let filter_args = FilterArgs {
+        filter: &[],
+        filter_regex: &[],
+        ids: &run_args.id,
+        exclude_filter: &[],
+        exclusion_file: &None,
+        refresh_discovery: false,
+        shard: None,
+    };
+    let app_tests = generate_app_tests(&filter_args, input_paths, &apps, false)?;
+    if !run_args.dry_run {
+        std::fs::create_dir_all(&output_paths.tmp_dir)?;
+    }
+    let tests = runnable::create_run_commands(input_paths, &app_tests, output_paths, run_args);
+    let instances: Vec<_> = tests
+        .iter()
+        .flat_map(|group| &group.tests)
+        .map(|creator| creator.instantiate())
+        .collect();
+    if instances.is_empty() {
+        return Err(eyre!("No test matches --id \"{}\".", run_args.id[0]));
+    }
+    Ok(instances)
+}
+
+fn cmd_debug(
+    instance: runnable::TestInstance,
+    input_paths: &config::InputPaths,
+    output_paths: &OutputPaths,
+) -> Result<()> {
+    let debugger = config::configured_debugger()?.unwrap_or_else(|| "gdb".to_string());
+    let mut debugger_args = debugger.split_whitespace();
+    let program = debugger_args.next().wrap_err("The debugger command is empty.")?;
+    let mut command = Command::new(program);
+    command.args(debugger_args);
+    if program.contains("lldb") {
+        command.arg("--");
+    } else {
+        command.arg("--args");
+    }
+    // the debugger resolves a relative executable from the test's cwd, not from ours
+    let exe = &instance.command.command[0];
+    let exe = std::fs::canonicalize(exe).unwrap_or_else(|_| PathBuf::from(exe));
+    command
+        .arg(exe)
+        .args(&instance.command.command[1..])
+        .current_dir(&instance.command.cwd)
+        .envs(instance.command.env.iter().cloned());
+    println!("{}", instance.command.shell_line());
+    // stdin, stdout and stderr stay attached to the terminal
+    let status = command
+        .status()
+        .wrap_err_with(|| format!("Failed to start the debugger '{}'.", debugger))?;
+    if !status.success() {
+        println!("The debugger exited with {}.", status);
+    }
+
+    if let (Some(tmp_path), Some(rel_path)) =
+        (&instance.command.tmp_path, &instance.test_id.rel_path)
+    {
+        let moved = report::move_artifact(
+            tmp_path,
+            rel_path,
+            &input_paths.testcases_dir,
+            &output_paths.out_dir,
+        )?;
+        for (_reference, artifact) in moved {
+            println!("Generated output: {}", artifact.display());
+        }
+    }
+    Ok(())
 }
 
 fn cmd_info(
@@ -481,9 +916,41 @@ fn cmd_info(
     apps_config: &config::AppsConfig,
     input_paths: &config::InputPaths,
 ) -> Result<()> {
-    let apps = apps_config
-        .clone()
-        .select_build_and_preset(&[name], &input_paths)?;
+    let apps = apps_config.clone().select_build(&[name], input_paths)?;
+
+    let capacities = config::resource_capacities()?;
+    let sources = &input_paths.sources;
+    let path_str = |p: &Option<PathBuf>| {
+        p.as_ref()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "<none>".to_string())
+    };
+    println!("Settings:");
+    println!(
+        "  dev dir: {} ({})",
+        path_str(&input_paths.dev_dir),
+        sources.dev_dir
+    );
+    println!(
+        "  build dir: {} ({})",
+        input_paths.build_dir.display(),
+        sources.build_dir
+    );
+    println!(
+        "  testcases dir: {} ({})",
+        input_paths.testcases_dir.display(),
+        sources.testcases_dir
+    );
+    println!(
+        "  build type: {} ({})",
+        input_paths.build_type.as_deref().unwrap_or("<none>"),
+        sources.build_type
+    );
+    println!(
+        "  config: {} ({})",
+        input_paths.build_config, sources.build_config
+    );
+    println!("  preset: {} ({})", input_paths.preset, sources.preset);
     for (name, app) in &apps.0 {
         let cfg = &apps_config.0[name];
 
@@ -491,6 +958,9 @@ fn cmd_info(
         println!("Aliases: {:?}", cfg.alias);
         println!("Tags: {:?}", cfg.tags);
         println!("Responsible: {}", app.responsible);
+        if let Some(max_parallel) = app.max_parallel {
+            println!("Max parallel: {}", max_parallel);
+        }
         if cfg.disabled {
             println!(
                 r#"
@@ -519,9 +989,15 @@ This test is currently disabled
                 if let Some(g) = &group.find_glob {
                     println!("    files: {}", g);
                 }
+                if let Some(g) = &group.find_parent_dir {
+                    println!("    directories containing: {}", g);
+                }
                 if let Some(g) = &group.find_gtest {
                     println!("    gtests: {}", g);
                 }
+                if let Some(s) = &group.input_strings {
+                    println!("    inputs: {:?}", s);
+                }
                 println!("    execution style: {}", group.execution_style);
                 println!("    timeout: {:?}", group.timeout);
                 println!("    timeout if changed: {:?}", group.timeout_if_changed);
@@ -531,6 +1007,21 @@ This test is currently disabled
                     group.testcases_dependencies
                 );
                 println!("    command: {:?}", group.command);
+                if !group.env.is_empty() {
+                    println!("    environment: {:?}", group.env_vars());
+                }
+                if !group.resources.is_empty() {
+                    let mut resources: Vec<_> = group
+                        .resources
+                        .iter()
+                        .map(|(name, amount)| {
+                            let capacity = capacities.get(name).copied().unwrap_or(1);
+                            format!("{} ({} of {})", name, amount, capacity)
+                        })
+                        .collect();
+                    resources.sort();
+                    println!("    resources: {}", resources.join(", "));
+                }
             }
         }
 
@@ -551,6 +1042,8 @@ pub struct AppWithTests {
     name: String,
     app: config::App,
     tests: Vec<GroupWithTests>,
+    /// tests that are excluded by the exclusion file, with the reason
+    skipped: Vec<(TestId, String)>,
 }
 
 #[derive(Debug)]
@@ -580,52 +1073,82 @@ pub struct OutputPaths {
 
 struct FilterArgs<'a> {
     filter: &'a [String],
+    filter_regex: &'a [String],
     ids: &'a [String],
+    exclude_filter: &'a [String],
     exclusion_file: &'a Option<String>,
+    refresh_discovery: bool,
+    shard: Option<shard::Shard>,
 }
 fn generate_app_tests(
     filter_args: &FilterArgs,
     input_paths: &config::InputPaths,
     apps_config: &config::Apps,
     can_run_raw_gtest: bool,
-) -> Vec<AppWithTests> {
-    let id_filter = id_filter_from_args(&filter_args).unwrap();
+) -> Result<Vec<AppWithTests>> {
+    let id_filter = id_filter_from_args(filter_args)?;
+    let exclusions = load_exclusions(filter_args.exclusion_file)?;
+    // list the tests of all gtest executables up front, so that this can run in parallel
+    let mut discovery =
+        discovery::GtestDiscovery::load(&input_paths.build_dir, filter_args.refresh_discovery);
+    let gtest_requests: Vec<_> = apps_config
+        .0
+        .values()
+        .flat_map(|app| {
+            app.tests
+                .iter()
+                .flat_map(|p| p.groups.iter())
+                .filter_map(move |g| g.gtest_request(app))
+        })
+        .collect();
+    discovery.discover(&gtest_requests);
     let apps: Vec<AppWithTests> = apps_config
         .0
         .iter()
         .map(|(app_name, app)| {
             // populate with tests
-            let tests: Vec<GroupWithTests> = app
-                .tests
-                .iter()
-                .flat_map(|preset_config| {
-                    preset_config
-                        .groups
-                        .iter()
-                        .map(|test_group| {
-                            let test_filter = if can_run_raw_gtest && app.supports_gtest_batching {
-                                test_group.find_gtest.clone()
-                            } else {
-                                None
-                            };
-                            GroupWithTests {
-                                test_group: test_group.clone(),
-                                command: test_group.command.clone(),
-                                test_ids: test_group
-                                    .generate_test_inputs(&app, &preset_config, &input_paths)
-                                    .into_iter()
-                                    .filter(|f| id_filter(&f.id))
-                                    .collect(),
-                                test_filter,
+            let mut skipped = vec![];
+            let mut tests = vec![];
+            for preset_config in &app.tests {
+                for test_group in &preset_config.groups {
+                    let test_filter = if can_run_raw_gtest && app.supports_gtest_batching {
+                        test_group.find_gtest.clone()
+                    } else {
+                        None
+                    };
+                    let mut test_ids = vec![];
+                    for test_id in
+                        test_group.generate_test_inputs(app, preset_config, input_paths, &discovery)
+                    {
+                        if !id_filter(&test_id.id) {
+                            continue;
+                        }
+                        if let Some(shard) = &filter_args.shard {
+                            if !shard.contains(app_name, &test_id.id) {
+                                continue;
                             }
-                        })
-                        .collect::<Vec<_>>()
-                })
-                .collect();
+                        }
+                        let exclusion = exclusions
+                            .as_ref()
+                            .and_then(|e| e.find(app_name, &test_id.id));
+                        match exclusion {
+                            Some(exclusion) => skipped.push((test_id, exclusion.description())),
+                            None => test_ids.push(test_id),
+                        }
+                    }
+                    tests.push(GroupWithTests {
+                        test_group: test_group.clone(),
+                        command: test_group.command.clone(),
+                        test_ids,
+                        test_filter,
+                    });
+                }
+            }
             AppWithTests {
                 name: app_name.to_string(),
                 app: (*app).clone(),
                 tests,
+                skipped,
             }
         })
         .filter(|app_with_tests| !app_with_tests.tests.is_empty())
@@ -633,53 +1156,274 @@ fn generate_app_tests(
     if apps.is_empty() {
         println!("WARNING: you have not selected any tests.");
     }
-    apps
+    Ok(apps)
 }
 
-fn id_filter_from_args<'a>(
-    filter_args: &'a FilterArgs<'a>,
-) -> Result<Box<dyn Fn(&str) -> bool + 'a>> {
+/// Loads the exclusion file and warns about entries that have expired.
+fn load_exclusions(exclusion_file: &Option<String>) -> Result<Option<exclusions::Exclusions>> {
+    let path = match exclusion_file {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let mut exclusions = exclusions::Exclusions::load(Path::new(path))
+        .wrap_err_with(|| format!("while loading exclusion file {}", path))?;
+    let today = chrono::Local::now().date_naive();
+    for expired in exclusions.remove_expired(today) {
+        println!(
+            "WARNING: exclusion of {} --id \"{}\" expired on {} ({}). The test is run again.",
+            expired.app,
+            expired.id,
+            expired.expires.unwrap(),
+            expired.reason.as_deref().unwrap_or("no reason given")
+        );
+    }
+    Ok(Some(exclusions))
+}
+
+type IdFilter<'a> = Box<dyn Fn(&str) -> bool + 'a>;
+
+/// Brackets are common in the ids of parameterized tests, so they are matched literally instead
+/// of being character classes.
+fn id_glob(id: &str) -> std::result::Result<glob::Pattern, glob::PatternError> {
+    let escaped: String = id
+        .chars()
+        .map(|c| match c {
+            '[' => "[[]".to_string(),
+            ']' => "[]]".to_string(),
+            c => c.to_string(),
+        })
+        .collect();
+    glob::Pattern::new(&escaped)
+}
+
+/// A test is selected if it matches any of --filter, --filter-regex and --id (or if none of them
+/// are given), and none of --exclude-filter. The exclusion file is applied separately, so that
+/// excluded tests can be reported as skipped.
+fn id_filter_from_args<'a>(filter_args: &'a FilterArgs<'a>) -> Result<IdFilter<'a>> {
     let normalize = |input: &str| input.to_lowercase().replace('\\', "/");
-    let exclusion_filter =
-        id_filter_from_exclusion_file(&filter_args).wrap_err("while loading exclusion file")?;
+    let filters: Vec<_> = filter_args.filter.iter().map(|f| normalize(f)).collect();
+    let exclude_filters: Vec<_> = filter_args
+        .exclude_filter
+        .iter()
+        .map(|f| normalize(f))
+        .collect();
+    let regexes = filter_args
+        .filter_regex
+        .iter()
+        .map(|r| {
+            regex::RegexBuilder::new(r)
+                .case_insensitive(true)
+                .build()
+                .wrap_err_with(|| format!("invalid --filter-regex '{}'", r))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let (id_globs, ids): (Vec<_>, Vec<_>) = filter_args
+        .ids
+        .iter()
+        .map(|i| normalize(i))
+        .partition(|i| i.contains(&['*', '?'][..]));
+    let id_globs = id_globs
+        .iter()
+        .map(|g| id_glob(g).wrap_err_with(|| format!("invalid --id '{}'", g)))
+        .collect::<Result<Vec<_>>>()?;
+    let glob_options = glob::MatchOptions {
+        case_sensitive: false,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    };
+    let has_positive_filter =
+        !filters.is_empty() || !regexes.is_empty() || !filter_args.ids.is_empty();
     Ok(Box::new(move |input: &str| {
         let input = normalize(input);
-        if !exclusion_filter(&input) {
+        if exclude_filters.iter().any(|f| input.contains(f.as_str())) {
             return false;
         }
-        if !filter_args.filter.is_empty() {
-            filter_args
-                .filter
+        !has_positive_filter
+            || filters.iter().any(|f| input.contains(f.as_str()))
+            || regexes.iter().any(|r| r.is_match(&input))
+            || ids.contains(&input)
+            || id_globs
                 .iter()
-                .any(|f| input.contains(normalize(f).as_str()))
-        } else if !filter_args.ids.is_empty() {
-            filter_args.ids.iter().any(|i| normalize(i) == input)
-        } else {
-            true
-        }
+                .any(|g| g.matches_with(&input, glob_options))
     }))
 }
 
-fn id_filter_from_exclusion_file(run_args: &FilterArgs) -> Result<Box<dyn Fn(&str) -> bool>> {
-    if let Some(exclusion_file) = run_args.exclusion_file {
-        let mut excluded = vec![];
-        let content = std::fs::read_to_string(exclusion_file)?;
-        for line in content.lines() {
-            let mut tokens = line.split(" --id ");
-            match (tokens.next(), tokens.next()) {
-                (Some(app), Some(id)) => {
-                    excluded.push((
-                        app.trim().to_string(),
-                        id.trim().trim_matches('"').to_string(),
-                    ));
-                }
-                _ => {} // ignore
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matching(ids: &[&str], filter_args: &FilterArgs) -> Vec<String> {
+        let filter = id_filter_from_args(filter_args).unwrap();
+        ids.iter()
+            .filter(|id| filter(id))
+            .map(|id| id.to_string())
+            .collect()
+    }
+
+    fn filter_args<'a>(
+        filter: &'a [String],
+        filter_regex: &'a [String],
+        ids: &'a [String],
+        exclude_filter: &'a [String],
+    ) -> FilterArgs<'a> {
+        FilterArgs {
+            filter,
+            filter_regex,
+            ids,
+            exclude_filter,
+            exclusion_file: &None,
+            refresh_discovery: false,
+            shard: None,
         }
-        Ok(Box::new(move |input: &str| {
-            !excluded.iter().any(|(_app, id)| id == input)
-        }))
-    } else {
-        Ok(Box::new(move |_input: &str| true))
+    }
+
+    const IDS: &[&str] = &[
+        "smoke/a/drill1",
+        "smoke/a/b/drill2",
+        "smoke/mill",
+        "Suite/Param[1].Case",
+        "Suite/Param[2].Case",
+        "Suite/Param1.Case",
+    ];
+
+    #[test]
+    fn id_filter() {
+        let strings = |s: &[&str]| -> Vec<String> { s.iter().map(|s| s.to_string()).collect() };
+        let none = [];
+        // no filter selects everything
+        assert_eq!(matching(IDS, &filter_args(&none, &none, &none, &none)), IDS);
+
+        let ids = strings(&["smoke/**/drill*", "SMOKE\\MILL"]);
+        assert_eq!(
+            matching(IDS, &filter_args(&none, &none, &ids, &none)),
+            ["smoke/a/drill1", "smoke/a/b/drill2", "smoke/mill"]
+        );
+        // '*' doesn't cross folders
+        let ids = strings(&["smoke/*/drill*"]);
+        assert_eq!(
+            matching(IDS, &filter_args(&none, &none, &ids, &none)),
+            ["smoke/a/drill1"]
+        );
+
+        let filters = strings(&["drill"]);
+        let regexes = strings(&["^smoke/m"]);
+        let excluded = strings(&["drill2"]);
+        assert_eq!(
+            matching(IDS, &filter_args(&filters, &regexes, &none, &excluded)),
+            ["smoke/a/drill1", "smoke/mill"]
+        );
+    }
+
+    #[test]
+    fn id_filter_brackets_are_literal() {
+        let ids = vec!["Suite/Param[1].Case".to_string()];
+        assert_eq!(
+            matching(IDS, &filter_args(&[], &[], &ids, &[])),
+            ["Suite/Param[1].Case"]
+        );
+        let ids = vec!["Suite/Param[*].Case".to_string()];
+        assert_eq!(
+            matching(IDS, &filter_args(&[], &[], &ids, &[])),
+            ["Suite/Param[1].Case", "Suite/Param[2].Case"]
+        );
+        // an unbalanced bracket is not an invalid pattern either
+        let ids = vec!["Suite/Param[?".to_string()];
+        assert!(matching(IDS, &filter_args(&[], &[], &ids, &[])).is_empty());
+    }
+
+    #[test]
+    fn resource_args() {
+        assert_eq!(
+            parse_resource(" license = 2").unwrap(),
+            ("license".to_string(), 2)
+        );
+        for invalid in &["license", "license=", "license=-1", "=2", " =2"] {
+            assert!(parse_resource(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    /// The tests of app "a" of `apps_json`, with the preset "ci" of build "b" in /build.
+    fn instances(apps_json: &str, run_args: &RunArgs) -> Vec<runnable::TestInstance> {
+        let apps: config::AppsConfig = serde_json::from_str(apps_json).unwrap();
+        let source = config::ValueSource::Default;
+        let input_paths = config::InputPaths {
+            dev_dir: None,
+            build_dir: PathBuf::from("/build"),
+            testcases_dir: PathBuf::from("/testcases"),
+            build_type: Some("b".to_string()),
+            preset: "ci".to_string(),
+            build_config: "Release".to_string(),
+            single_config: false,
+            sources: config::InputSources {
+                dev_dir: source.clone(),
+                build_dir: source.clone(),
+                testcases_dir: source.clone(),
+                build_type: source.clone(),
+                preset: source.clone(),
+                build_config: source,
+            },
+        };
+        let output_paths = OutputPaths {
+            out_dir: PathBuf::from("/out"),
+            tmp_dir: PathBuf::from("/out/tmp"),
+        };
+        instantiate_tests(apps, &input_paths, &output_paths, "a".to_string(), run_args).unwrap()
+    }
+
+    #[test]
+    fn input_strings() {
+        let apps_json = r#"{ "a": { "command": ["{{exe}}", "--case={{input}}"], "responsible": "me",
+                 "builds": { "b": { "exe": "x", "cwd": "{{build_dir}}/work" } },
+                 "tests": { "ci": { "groups": [ { "input_strings": ["first", "second"] } ] } } } }"#;
+        let mut run_args = single_test_args("*", vec![]);
+        run_args.dry_run = true;
+        let instances = instances(apps_json, &run_args);
+        let ids: Vec<_> = instances.iter().map(|i| i.test_id.id.as_str()).collect();
+        assert_eq!(ids, ["first", "second"]);
+        for instance in &instances {
+            assert_eq!(
+                instance.command.command[1],
+                format!("--case={}", instance.test_id.id)
+            );
+            assert_eq!(instance.command.cwd, "/build/work");
+        }
+    }
+
+    /// The command of the single test of an app with a wrapper and a log folder.
+    fn wrapped_command(run_args: &RunArgs) -> runnable::TestCommand {
+        let mut instances = instances(
+            r#"{ "a": { "command": ["{{exe}}", "{{input}}"], "responsible": "me",
+                 "builds": { "b": { "exe": "x" } },
+                 "tests": { "ci": { "groups": [ { "input_strings": ["i"],
+                     "wrapper": ["{{build_dir}}/wrap", "--log={{wrapper_log_dir}}/w.log"] } ] } } } }"#,
+            run_args,
+        );
+        assert_eq!(instances.len(), 1);
+        instances.remove(0).command
+    }
+
+    #[test]
+    fn wrappers() {
+        let mut run_args = single_test_args("*", vec![]);
+        run_args.dry_run = true;
+        let command = wrapped_command(&run_args);
+        let log_dir = command.wrapper_log_dir.unwrap();
+        assert!(log_dir.starts_with("/out/tmp"));
+        assert_eq!(command.command[0], "/build/wrap");
+        assert_eq!(
+            command.command[1],
+            format!("--log={}/w.log", log_dir.display())
+        );
+
+        // --wrapper replaces the wrapper of the group, and is expanded like it
+        run_args.wrapper = Some(parse_wrapper(r#""{{build_dir}}/my wrap" --"#).unwrap());
+        let command = wrapped_command(&run_args);
+        assert_eq!(command.command[..2], ["/build/my wrap", "--"]);
+        assert_eq!(command.wrapper_log_dir, None);
+
+        run_args.no_wrapper = true;
+        let command = wrapped_command(&run_args);
+        assert_eq!(command.command.len(), 2);
+        assert_eq!(command.wrapper_log_dir, None);
     }
 }
@@ -0,0 +1,323 @@
+use crate::config::Outcome;
+use crate::report::Summary;
+use serde::Deserialize;
+use simple_eyre::eyre::{eyre, Result, WrapErr};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename = "testsuites")]
+struct TestSuites {
+    mw_paths: Option<MwPaths>,
+    #[serde(rename = "testsuite", default)]
+    testsuites: Vec<TestSuite>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MwPaths {
+    testcases_root: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct TestSuite {
+    name: String,
+    #[serde(rename = "testcase", default)]
+    testcases: Vec<TestCase>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TestCase {
+    name: String,
+    #[serde(rename = "exit-code")]
+    exit_code: Option<i32>,
+    outcome: Option<String>,
+    failure: Option<Empty>,
+    skipped: Option<Skipped>,
+    #[serde(rename = "system-out")]
+    system_out: Option<String>,
+    #[serde(rename = "artifact", default)]
+    artifacts: Vec<Artifact>,
+    #[serde(rename = "wrapper-log", default)]
+    wrapper_logs: Vec<WrapperLog>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Empty {}
+
+#[derive(Deserialize, Debug)]
+struct Skipped {
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Artifact {
+    reference: String,
+    location: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct WrapperLog {
+    location: String,
+}
+
+impl TestCase {
+    fn outcome(&self) -> Outcome {
+        match self.outcome.as_deref() {
+            Some("pass") => Outcome::Pass,
+            Some("fail") => Outcome::Fail,
+            Some("skipped") => Outcome::Skipped,
+            Some("known-issue") => Outcome::KnownIssue,
+            // written before outcomes existed
+            _ if self.failure.is_some() => Outcome::Fail,
+            _ => Outcome::Pass,
+        }
+    }
+}
+
+/// Combines the results.xml, artifacts and logs of several "mwtest run" output dirs (like the
+/// shards of one run) into `out_dir`. Returns whether all tests succeeded.
+pub fn merge_results(out_dir: &Path, in_dirs: &[PathBuf]) -> Result<bool> {
+    if in_dirs.iter().any(|in_dir| in_dir == out_dir) {
+        return Err(eyre!(
+            "ERROR: the output directory {:?} can't also be an input directory.",
+            out_dir
+        ));
+    }
+    if out_dir.exists() {
+        if !out_dir.join("results.xml").exists() {
+            return Err(eyre!(
+                "ERROR: can't reset the output directory: {:?}.\n It doesn't look like it \
+                 was created by mwtest. Please select another one or delete it manually.",
+                out_dir
+            ));
+        }
+        remove_dir_all::remove_dir_all(out_dir)?;
+    }
+    std::fs::create_dir_all(out_dir)?;
+
+    let mut testcases_root: Option<String> = None;
+    // per app
+    let mut suites: BTreeMap<String, Vec<TestCase>> = BTreeMap::new();
+    let mut logs: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for in_dir in in_dirs {
+        let results_path = in_dir.join("results.xml");
+        let content = std::fs::read_to_string(&results_path)
+            .wrap_err_with(|| format!("failed to read {}", results_path.display()))?;
+        let results: TestSuites = serde_xml_rs::from_str(&content)
+            .map_err(|e| eyre!("{}: {}", results_path.display(), e))?;
+
+        if let Some(paths) = results.mw_paths {
+            match &testcases_root {
+                Some(root) if *root != paths.testcases_root => println!(
+                    "WARNING: {} uses the testcases dir {}, expected {}",
+                    results_path.display(),
+                    paths.testcases_root,
+                    root
+                ),
+                Some(_) => {}
+                None => testcases_root = Some(paths.testcases_root),
+            }
+        }
+
+        for suite in results.testsuites {
+            let log_path = in_dir.join(format!("{}.txt", suite.name));
+            if let Ok(log) = std::fs::read(&log_path) {
+                logs.entry(suite.name.clone()).or_default().extend(log);
+            }
+            let mut testcases = suite.testcases;
+            for testcase in &mut testcases {
+                for artifact in &mut testcase.artifacts {
+                    artifact.location = copy_artifact(in_dir, &artifact.location, out_dir)?;
+                }
+                for log in &mut testcase.wrapper_logs {
+                    log.location = copy_artifact(in_dir, &log.location, out_dir)?;
+                }
+            }
+            suites.entry(suite.name).or_default().extend(testcases);
+        }
+    }
+
+    for (app_name, log) in &logs {
+        std::fs::write(out_dir.join(format!("{}.txt", app_name)), log)?;
+    }
+    write_results(
+        &out_dir.join("results.xml"),
+        testcases_root.as_deref().unwrap_or(""),
+        &suites,
+    )?;
+
+    let mut summary = Summary::new();
+    for (app_name, testcases) in &suites {
+        for testcase in testcases {
+            match (&testcase.exit_code, &testcase.skipped) {
+                // not run at all
+                (None, Some(skipped)) => summary.add_skipped(
+                    app_name,
+                    &testcase.name,
+                    skipped.message.as_deref().unwrap_or("skipped"),
+                ),
+                (exit_code, _) => summary.add(
+                    app_name,
+                    &testcase.name,
+                    testcase.outcome(),
+                    exit_code.unwrap_or(0),
+                ),
+            }
+        }
+    }
+    Ok(summary.print())
+}
+
+/// Copies an artifact (file or folder) of `in_dir` to the same relative location in `out_dir`,
+/// or next to it if that is taken already. Returns the new relative location.
+fn copy_artifact(in_dir: &Path, location: &str, out_dir: &Path) -> Result<String> {
+    let from = in_dir.join(location);
+    let mut to = out_dir.join(location);
+    let mut i = 1;
+    while to.exists() {
+        i += 1;
+        let file_name = Path::new(location).file_name().unwrap().to_string_lossy();
+        to.set_file_name(format!("{}.{}", file_name, i));
+    }
+    copy_recursively(&from, &to)
+        .wrap_err_with(|| format!("failed to copy artifact {}", from.display()))?;
+    Ok(to
+        .strip_prefix(out_dir)
+        .unwrap()
+        .to_string_lossy()
+        .into_owned())
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to.parent().unwrap())?;
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        std::fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+/// Writes the same format as the XML report of "mwtest run".
+fn write_results(
+    path: &Path,
+    testcases_root: &str,
+    suites: &BTreeMap<String, Vec<TestCase>>,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n")?;
+    writeln!(out, "<mw_paths testcases_root=\"{}\" />", testcases_root)?;
+    for (name, testcases) in suites {
+        writeln!(
+            out,
+            "<testsuite name=\"{}\" test=\"{}\">",
+            name,
+            testcases.len()
+        )?;
+        for testcase in testcases {
+            writeln!(
+                out,
+                "<testcase name=\"{}\">",
+                htmlescape::encode_attribute(&testcase.name)
+            )?;
+            if let Some(exit_code) = testcase.exit_code {
+                writeln!(out, "<exit-code>{}</exit-code>", exit_code)?;
+                writeln!(out, "<outcome>{}</outcome>", testcase.outcome())?;
+            }
+            if testcase.failure.is_some() {
+                out.write_all(b"<failure />")?;
+            }
+            match &testcase.skipped {
+                Some(Skipped {
+                    message: Some(message),
+                }) => writeln!(
+                    out,
+                    "<skipped message=\"{}\" />",
+                    htmlescape::encode_attribute(message)
+                )?,
+                Some(Skipped { message: None }) => out.write_all(b"<skipped />")?,
+                None => {}
+            }
+            if let Some(system_out) = &testcase.system_out {
+                writeln!(
+                    out,
+                    "<system-out>{}</system-out>",
+                    htmlescape::encode_minimal(system_out)
+                )?;
+            }
+            for artifact in &testcase.artifacts {
+                write!(
+                    out,
+                    "<artifact reference=\"{}\" location=\"{}\" />",
+                    htmlescape::encode_attribute(&artifact.reference),
+                    htmlescape::encode_attribute(&artifact.location)
+                )?;
+            }
+            for log in &testcase.wrapper_logs {
+                write!(
+                    out,
+                    "<wrapper-log location=\"{}\" />",
+                    htmlescape::encode_attribute(&log.location)
+                )?;
+            }
+            out.write_all(b"</testcase>\n")?;
+        }
+        out.write_all(b"</testsuite>\n")?;
+    }
+    out.write_all(b"</testsuites>\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_report() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+<mw_paths testcases_root="/t" />
+<testsuite name="app" test="3">
+<testcase name="a&#x2F;b">
+<exit-code>1</exit-code>
+<outcome>fail</outcome>
+<failure /><system-out>line &lt;1&gt;
+</system-out>
+<artifact reference="a/b" location="different/a/b" /><artifact reference="a/c" location="different/a/c" /><wrapper-log location="wrapper_logs/app/a_b/vg.log" /></testcase>
+<testcase name="c">
+<exit-code>0</exit-code>
+<system-out></system-out>
+</testcase>
+<testcase name="d">
+<skipped message="flaky" />
+</testcase>
+</testsuite>
+</testsuites>
+"#;
+        let results: TestSuites = serde_xml_rs::from_str(xml).unwrap();
+        assert_eq!(results.mw_paths.unwrap().testcases_root, "/t");
+        let testcases = &results.testsuites[0].testcases;
+        assert_eq!(testcases.len(), 3);
+        assert_eq!(testcases[0].name, "a/b");
+        assert_eq!(testcases[0].outcome(), Outcome::Fail);
+        // serde-xml-rs trims surrounding whitespace
+        assert_eq!(testcases[0].system_out.as_deref(), Some("line <1>"));
+        assert_eq!(testcases[0].artifacts[1].location, "different/a/c");
+        assert_eq!(
+            testcases[0].wrapper_logs[0].location,
+            "wrapper_logs/app/a_b/vg.log"
+        );
+        assert_eq!(testcases[1].outcome(), Outcome::Pass);
+        assert_eq!(testcases[2].exit_code, None);
+        assert_eq!(
+            testcases[2].skipped.as_ref().unwrap().message.as_deref(),
+            Some("flaky")
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use crate::config::Outcome;
+use crate::history::DurationHistory;
 use crate::runnable;
 use simple_eyre::eyre::{Result, WrapErr};
 use std::collections::{hash_map, HashMap};
@@ -20,23 +22,36 @@ pub struct Report {
     std_out: CliLogger,
     file_logger: FileLogger,
     xml_report: XmlReport,
+    history: DurationHistory,
     i: usize,
     n: usize,
 }
 impl Report {
-    pub fn new(artifacts_root: &Path, testcases_root: &str, verbose: bool) -> Result<Report> {
+    pub fn new(
+        artifacts_root: &Path,
+        testcases_root: &str,
+        verbose: bool,
+        history: DurationHistory,
+    ) -> Result<Report> {
         let xml_location = &artifacts_root.join("results.xml");
         let report = Report {
             std_out: CliLogger::create(verbose),
-            file_logger: FileLogger::new(&artifacts_root),
-            xml_report: XmlReport::create(&xml_location, &artifacts_root, &testcases_root)
+            file_logger: FileLogger::new(artifacts_root),
+            xml_report: XmlReport::create(xml_location, artifacts_root, testcases_root)
                 .wrap_err("failed to create XML report")?,
+            history,
             i: 0,
             n: 0,
         };
         report.std_out.init();
         Ok(report)
     }
+
+    /// Records a test that was not run, like those named in the exclusion file.
+    pub fn add_skipped(&mut self, app_name: &str, test_id: &str, reason: &str) {
+        self.std_out.add_skipped(app_name, test_id, reason);
+        self.xml_report.add_skipped(app_name, test_id, reason);
+    }
 }
 impl Reportable for Report {
     fn expect_additional_tests(&mut self, n: usize) {
@@ -51,15 +66,24 @@ impl Reportable for Report {
     ) {
         self.i += 1;
         self.std_out
-            .add(self.i, self.n, app_name, &test_instance, &test_result);
+            .add(self.i, self.n, app_name, &test_instance, test_result);
         self.file_logger.add(app_name, &test_result.stdout);
-        self.xml_report.add(app_name, test_instance, &test_result);
+        self.history
+            .record(app_name, &test_instance.test_id.id, test_result.duration);
+        self.xml_report.add(app_name, test_instance, test_result);
+    }
+}
+impl Drop for Report {
+    fn drop(&mut self) {
+        self.history.save();
     }
 }
 
 struct XmlReport {
     file: File,
     results: HashMap<String, Vec<(runnable::TestInstance, runnable::TestCommandResult)>>,
+    /// test ids and reasons per app
+    skipped: HashMap<String, Vec<(String, String)>>,
     artifacts_root: PathBuf,
     testcases_root: PathBuf,
 }
@@ -70,8 +94,9 @@ impl XmlReport {
         testcases_root: &str,
     ) -> std::io::Result<XmlReport> {
         Ok(XmlReport {
-            file: File::create(&path)?,
+            file: File::create(path)?,
             results: HashMap::new(),
+            skipped: HashMap::new(),
             artifacts_root: PathBuf::from(artifacts_root),
             testcases_root: PathBuf::from(testcases_root),
         })
@@ -93,6 +118,13 @@ impl XmlReport {
         }
     }
 
+    fn add_skipped(&mut self, app_name: &str, test_id: &str, reason: &str) {
+        self.skipped
+            .entry(app_name.to_string())
+            .or_default()
+            .push((test_id.to_string(), reason.to_string()));
+    }
+
     fn write(&mut self) -> std::io::Result<()> {
         let mut out = BufWriter::new(&self.file);
         out.write_all(b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n")?;
@@ -103,17 +135,32 @@ impl XmlReport {
             )
             .as_bytes(),
         )?;
-        for (test_name, test_results) in &self.results {
+        let mut test_names: Vec<_> = self.results.keys().chain(self.skipped.keys()).collect();
+        test_names.sort();
+        test_names.dedup();
+        for test_name in test_names {
+            let test_results = self.results.get(test_name).map_or(&[][..], |r| &r[..]);
+            let skipped = self.skipped.get(test_name).map_or(&[][..], |s| &s[..]);
             out.write_all(
                 format!(
                     "<testsuite name=\"{}\" test=\"{}\">\n",
                     test_name,
-                    test_results.len()
+                    test_results.len() + skipped.len()
                 )
                 .as_bytes(),
             )?;
             for (test_instance, command_result) in test_results.iter() {
-                self.write_testcase(&mut out, &test_instance, &command_result)?;
+                self.write_testcase(&mut out, test_name, test_instance, command_result)?;
+            }
+            for (test_id, reason) in skipped {
+                out.write_all(
+                    format!(
+                        "<testcase name=\"{}\">\n<skipped message=\"{}\" />\n</testcase>\n",
+                        htmlescape::encode_attribute(test_id),
+                        htmlescape::encode_attribute(reason)
+                    )
+                    .as_bytes(),
+                )?;
             }
             out.write_all(b"</testsuite>\n")?;
         }
@@ -124,6 +171,7 @@ impl XmlReport {
     fn write_testcase(
         &self,
         out: &mut BufWriter<&File>,
+        app_name: &str,
         test_instance: &runnable::TestInstance,
         command_result: &runnable::TestCommandResult,
     ) -> std::io::Result<()> {
@@ -135,8 +183,11 @@ impl XmlReport {
             .as_bytes(),
         )?;
         out.write_all(format!("<exit-code>{}</exit-code>\n", command_result.exit_code).as_bytes())?;
-        if command_result.exit_code != 0 {
-            out.write_all(b"<failure />")?;
+        out.write_all(format!("<outcome>{}</outcome>\n", command_result.outcome).as_bytes())?;
+        match command_result.outcome {
+            Outcome::Fail => out.write_all(b"<failure />")?,
+            Outcome::Skipped => out.write_all(b"<skipped />")?,
+            Outcome::Pass | Outcome::KnownIssue => {}
         }
         out.write_all(
             format!(
@@ -146,7 +197,11 @@ impl XmlReport {
             .as_bytes(),
         )?;
         if let Some(tmp_path) = &test_instance.command.tmp_path {
-            self.move_artifact(&tmp_path, out, &test_instance, &command_result)
+            self.move_artifact(tmp_path, out, test_instance, command_result)
+                .unwrap_or_else(|e| println!("INFO: {:?}", e));
+        }
+        if let Some(log_dir) = &test_instance.command.wrapper_log_dir {
+            self.move_wrapper_logs(log_dir, out, app_name, &test_instance.test_id.id)
                 .unwrap_or_else(|e| println!("INFO: {:?}", e));
         }
         out.write_all(b"</testcase>\n")?;
@@ -160,51 +215,73 @@ impl XmlReport {
         test_instance: &runnable::TestInstance,
         command_result: &runnable::TestCommandResult,
     ) -> std::io::Result<()> {
-        if tmp_path.exists() {
-            let rel_path = test_instance.test_id.rel_path.as_ref().unwrap();
-            let abs_reference_path = self.testcases_root.join(rel_path);
-            let sub_dir = if command_result.exit_code == 0 {
-                "success"
-            } else {
-                "different"
-            };
-            let mut abs_artifact_path = self.artifacts_root.join(sub_dir).join(rel_path);
-            if abs_artifact_path.exists() {
-                abs_artifact_path.set_file_name(
-                    abs_artifact_path
-                        .file_name()
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                        .to_string()
-                        + &Uuid::new_v4().to_string(),
-                );
-            }
-
-            if abs_reference_path.is_dir() || tmp_path.is_file() {
-                std::fs::create_dir_all(abs_artifact_path.parent().unwrap())?;
-                std::fs::rename(&tmp_path, &abs_artifact_path)?;
-                self.write_artifact(out, &abs_reference_path, &abs_artifact_path)?;
-            } else {
-                let abs_artifact_dir = abs_artifact_path.parent().unwrap();
-                let abs_reference_dir = abs_reference_path.parent().unwrap();
-                if std::fs::read_dir(tmp_path)?.next().is_some() {
-                    std::fs::create_dir_all(&abs_artifact_dir)?;
-                }
-                for entry in std::fs::read_dir(tmp_path)? {
-                    let from = entry?.path();
-                    let file_name = &from.file_name().unwrap();
-                    let to = abs_artifact_dir.join(file_name);
-                    std::fs::rename(&from, &to)?;
-                    let reference = abs_reference_dir.join(to.file_name().unwrap());
-                    self.write_artifact(out, &reference, &to)?;
-                }
-                std::fs::remove_dir(tmp_path)?;
-            }
+        let rel_path = test_instance.test_id.rel_path.as_ref().unwrap();
+        let sub_dir = if command_result.outcome == Outcome::Pass {
+            "success"
+        } else {
+            "different"
+        };
+        let moved = move_artifact(
+            tmp_path,
+            rel_path,
+            &self.testcases_root,
+            &self.artifacts_root.join(sub_dir),
+        )?;
+        for (abs_reference_path, abs_artifact_path) in moved {
+            self.write_artifact(out, &abs_reference_path, &abs_artifact_path)?;
         }
         Ok(())
     }
 
+    /// Moves the files that a wrapper wrote to `<artifacts_root>/wrapper_logs/<app>/<id>`.
+    fn move_wrapper_logs(
+        &self,
+        log_dir: &Path,
+        out: &mut BufWriter<&File>,
+        app_name: &str,
+        test_id: &str,
+    ) -> std::io::Result<()> {
+        // the tests of a gtest process share the folder, it is moved with the first one
+        if !log_dir.is_dir() {
+            return Ok(());
+        }
+        let safe_id: String = test_id
+            .chars()
+            .map(|c| if c.is_alphanumeric() || "-_.".contains(c) { c } else { '_' })
+            .collect();
+        let mut target_dir = self
+            .artifacts_root
+            .join("wrapper_logs")
+            .join(app_name)
+            .join(safe_id);
+        if target_dir.exists() {
+            // repeated runs
+            target_dir.set_file_name(format!(
+                "{}{}",
+                target_dir.file_name().unwrap().to_str().unwrap(),
+                Uuid::new_v4()
+            ));
+        }
+        let mut entries = std::fs::read_dir(log_dir)?.peekable();
+        if entries.peek().is_some() {
+            std::fs::create_dir_all(&target_dir)?;
+        }
+        for entry in entries {
+            let from = entry?.path();
+            let to = target_dir.join(from.file_name().unwrap());
+            std::fs::rename(&from, &to)?;
+            let rel_path = to.strip_prefix(&self.artifacts_root).unwrap();
+            out.write_all(
+                format!(
+                    "<wrapper-log location=\"{}\" />",
+                    htmlescape::encode_attribute(rel_path.to_str().unwrap())
+                )
+                .as_bytes(),
+            )?;
+        }
+        std::fs::remove_dir(log_dir)
+    }
+
     fn write_artifact(
         &self,
         out: &mut BufWriter<&File>,
@@ -224,15 +301,65 @@ impl XmlReport {
         out.write_all(
             format!(
                 "<artifact reference=\"{}\" location=\"{}\" />",
-                htmlescape::encode_attribute(&rel_reference_path),
-                htmlescape::encode_attribute(&rel_artifact_path)
+                htmlescape::encode_attribute(rel_reference_path),
+                htmlescape::encode_attribute(rel_artifact_path)
             )
             .as_bytes(),
         )?;
         Ok(())
     }
 }
-impl<'a> Drop for XmlReport {
+
+/// Moves the generated output of a test (file or folder) from `tmp_path` into `artifacts_dir`, to
+/// the same relative path as the test input in the testcases dir. Returns pairs of reference and
+/// artifact paths.
+pub fn move_artifact(
+    tmp_path: &Path,
+    rel_path: &Path,
+    testcases_root: &Path,
+    artifacts_dir: &Path,
+) -> std::io::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut moved = vec![];
+    if tmp_path.exists() {
+        let abs_reference_path = testcases_root.join(rel_path);
+        let mut abs_artifact_path = artifacts_dir.join(rel_path);
+        if abs_artifact_path.exists() {
+            abs_artifact_path.set_file_name(
+                abs_artifact_path
+                    .file_name()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+                    + &Uuid::new_v4().to_string(),
+            );
+        }
+
+        if abs_reference_path.is_dir() || tmp_path.is_file() {
+            std::fs::create_dir_all(abs_artifact_path.parent().unwrap())?;
+            std::fs::rename(tmp_path, &abs_artifact_path)?;
+            moved.push((abs_reference_path, abs_artifact_path));
+        } else {
+            let abs_artifact_dir = abs_artifact_path.parent().unwrap();
+            let abs_reference_dir = abs_reference_path.parent().unwrap();
+            if std::fs::read_dir(tmp_path)?.next().is_some() {
+                std::fs::create_dir_all(abs_artifact_dir)?;
+            }
+            for entry in std::fs::read_dir(tmp_path)? {
+                let from = entry?.path();
+                let file_name = &from.file_name().unwrap();
+                let to = abs_artifact_dir.join(file_name);
+                std::fs::rename(&from, &to)?;
+                let reference = abs_reference_dir.join(to.file_name().unwrap());
+                moved.push((reference, to));
+            }
+            std::fs::remove_dir(tmp_path)?;
+        }
+    }
+    Ok(moved)
+}
+
+impl Drop for XmlReport {
     fn drop(&mut self) {
         self.write().expect("failed to write xml log!");
     }
@@ -241,19 +368,14 @@ impl<'a> Drop for XmlReport {
 struct CliLogger {
     verbose: bool,
     term_width: Option<usize>,
-    run_counts: HashMap<TestUid, RunCount>,
-}
-type TestUid = (String, String);
-struct RunCount {
-    n_runs: u32,
-    n_successes: u32,
+    summary: Summary,
 }
 impl CliLogger {
     fn create(verbose: bool) -> CliLogger {
         CliLogger {
             verbose,
             term_width: term_size::dimensions_stdout().map(|(w, _h)| w),
-            run_counts: HashMap::new(),
+            summary: Summary::new(),
         }
     }
 
@@ -273,10 +395,11 @@ impl CliLogger {
         let id = &test_instance.test_id.id;
 
         // generate progress message
-        let ok_or_failed = if result.exit_code == 0 {
-            "Ok"
-        } else {
-            "Failed"
+        let ok_or_failed = match result.outcome {
+            Outcome::Pass => "Ok",
+            Outcome::Fail => "Failed",
+            Outcome::Skipped => "Skipped",
+            Outcome::KnownIssue => "Known issue",
         };
         let mut line = format!("[{}/{}] {}: {} --id \"{}\"", i, n, ok_or_failed, &name, &id);
 
@@ -294,7 +417,7 @@ impl CliLogger {
         }
 
         // print full test output if requested
-        if result.exit_code != 0 || self.verbose {
+        if result.outcome == Outcome::Fail || self.verbose {
             println!("\n{}\n", &result.stdout.trim());
         }
 
@@ -303,6 +426,43 @@ impl CliLogger {
             std::io::stdout().flush().unwrap();
         }
 
+        self.summary.add(name, id, result.outcome, result.exit_code);
+    }
+
+    fn add_skipped(&mut self, name: &str, id: &str, reason: &str) {
+        self.summary.add_skipped(name, id, reason);
+    }
+}
+impl Drop for CliLogger {
+    fn drop(&mut self) {
+        if !self.verbose {
+            println!();
+        }
+        self.summary.print();
+    }
+}
+
+/// Counts the runs of each test and prints failed, instable and skipped tests at the end.
+pub struct Summary {
+    run_counts: HashMap<TestUid, RunCount>,
+    skipped: Vec<(TestUid, String)>,
+    known_issues: Vec<(TestUid, String)>,
+}
+type TestUid = (String, String);
+struct RunCount {
+    n_runs: u32,
+    n_successes: u32,
+}
+impl Summary {
+    pub fn new() -> Summary {
+        Summary {
+            run_counts: HashMap::new(),
+            skipped: vec![],
+            known_issues: vec![],
+        }
+    }
+
+    pub fn add(&mut self, name: &str, id: &str, outcome: Outcome, exit_code: i32) {
         let entry = self
             .run_counts
             .entry((name.to_string(), id.to_string()))
@@ -311,12 +471,29 @@ impl CliLogger {
                 n_successes: 0,
             });
         entry.n_runs += 1;
-        if result.exit_code == 0 {
+        if outcome.is_success() {
             entry.n_successes += 1;
         }
+        let uid = (name.to_string(), id.to_string());
+        match outcome {
+            Outcome::Skipped => self
+                .skipped
+                .push((uid, format!("skipped by the test, exit code {}", exit_code))),
+            Outcome::KnownIssue => self
+                .known_issues
+                .push((uid, format!("exit code {}", exit_code))),
+            Outcome::Pass | Outcome::Fail => {}
+        }
+    }
+
+    /// For tests that were not run at all, like those in the exclusion file.
+    pub fn add_skipped(&mut self, name: &str, id: &str, reason: &str) {
+        self.skipped
+            .push(((name.to_string(), id.to_string()), reason.to_string()));
     }
 
-    fn report_summary(&self) -> bool {
+    /// Returns whether all tests succeeded (at least once).
+    pub fn print(&self) -> bool {
         let test_formatter = |(id, run_counts): (&TestUid, &RunCount)| {
             if run_counts.n_runs > 1 {
                 format!(
@@ -347,6 +524,25 @@ impl CliLogger {
         instable.sort_unstable();
         let none_instable = instable.is_empty();
 
+        let reason_formatter =
+            |(id, reason): &(TestUid, String)| format!("  {} --id \"{}\" ({})", id.0, id.1, reason);
+        for (title, tests) in [
+            ("Tests that were skipped: ", &self.skipped),
+            ("Tests with known issues: ", &self.known_issues),
+        ]
+        .iter()
+        {
+            let mut tests: Vec<String> = tests.iter().map(reason_formatter).collect();
+            tests.sort_unstable();
+            tests.dedup();
+            if !tests.is_empty() {
+                println!("{}", title);
+                for t in tests {
+                    println!("{}", t);
+                }
+            }
+        }
+
         if !none_instable {
             println!("Tests that are instable: ");
             for t in instable {
@@ -372,14 +568,6 @@ impl CliLogger {
         all_succeeded
     }
 }
-impl Drop for CliLogger {
-    fn drop(&mut self) {
-        if !self.verbose {
-            println!();
-        }
-        self.report_summary();
-    }
-}
 
 struct FileLogger {
     log_dir: PathBuf,
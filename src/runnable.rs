@@ -1,5 +1,6 @@
 use crate::config;
 use crate::TestId;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use tokio::process::Command;
 use uuid::Uuid;
@@ -11,16 +12,36 @@ pub fn create_run_commands(
     run_args: &crate::RunArgs,
 ) -> Vec<TestGroup> {
     let mut tests: Vec<TestGroup> = Vec::new();
+    // like the wrappers in apps.json, --wrapper may use {{build_dir}} and the like
+    let cli_wrapper = run_args
+        .wrapper
+        .as_ref()
+        .map(|wrapper| wrapper.apply_input_paths(input_paths));
     for app in test_apps {
         for group in &app.tests {
             let execution_style = match group.test_group.execution_style.as_ref() {
                 "singlethreaded" => ExecutionStyle::Single,
                 "parallel" => ExecutionStyle::Parallel,
-                "xge" => ExecutionStyle::XGE,
+                "xge" => ExecutionStyle::Xge,
                 _ => panic!(
                     "Invalid execution style! Only 'singlethreaded', 'parallel', 'xge' allowed."
                 ),
             };
+            // --wrapper replaces the wrapper of the group, including its timeout factor
+            let (wrapper, wrapper_timeout_factor) = match &cli_wrapper {
+                _ if run_args.no_wrapper => (None, None),
+                Some(wrapper) => (Some(wrapper), run_args.wrapper_timeout_factor),
+                None => (
+                    group.test_group.wrapper.as_ref(),
+                    run_args
+                        .wrapper_timeout_factor
+                        .or(group.test_group.wrapper_timeout_factor),
+                ),
+            };
+            let timeout_factor = match wrapper {
+                Some(_) => run_args.timeout_factor * wrapper_timeout_factor.unwrap_or(1.0),
+                None => run_args.timeout_factor,
+            };
             let timeout = if run_args.no_timeout {
                 None
             } else if let Some(timeout) = run_args.timeout {
@@ -29,22 +50,37 @@ pub fn create_run_commands(
                 group
                     .test_group
                     .timeout_if_changed
-                    .map(|t| t * run_args.timeout_factor)
+                    .map(|t| t * timeout_factor)
             } else {
-                group
-                    .test_group
-                    .timeout
-                    .map(|t| t * run_args.timeout_factor)
+                group.test_group.timeout.map(|t| t * timeout_factor)
             };
 
+            let mut command = group.command.with_extra_args(&run_args.extra_args);
+            if let Some(wrapper) = wrapper {
+                command = command.with_wrapper(wrapper);
+            }
+            let with_log_dir = |generator| match wrapper {
+                Some(_) => with_wrapper_log_dir(
+                    generator,
+                    output_paths.tmp_dir.clone(),
+                    !run_args.dry_run,
+                ),
+                None => generator,
+            };
             let gtest_generator = match &group.test_filter {
                 Some(test_filter) => {
                     let test_id = TestId {
                         id: test_filter.clone(),
                         rel_path: None,
                     };
-                    let (_input_str, cwd) = test_id_to_input(&test_id, &input_paths, &app.app);
-                    Some(gtest_command_generator(&group.command, &test_filter, cwd))
+                    let (_input_str, cwd) =
+                        test_id_to_input(&test_id, input_paths, &app.app, &group.test_group);
+                    Some(with_log_dir(gtest_command_generator(
+                        &command,
+                        test_filter,
+                        cwd,
+                        group.test_group.env_vars(),
+                    )))
                 }
                 None => None,
             };
@@ -52,13 +88,13 @@ pub fn create_run_commands(
             let changed_paths = run_args
                 .run_only_changed_file
                 .as_ref()
-                .map(|path| load_paths_from_file(&path));
+                .map(|path| load_paths_from_file(path));
 
             let excluded_paths = group
                 .test_group
                 .exclusion_list
                 .as_ref()
-                .map(|path| load_paths_from_file(&path));
+                .map(|path| load_paths_from_file(path));
 
             let mut test_generators = Vec::new();
             for test_id in &group.test_ids {
@@ -85,17 +121,21 @@ pub fn create_run_commands(
                         }
                     }
                 }
-                let (input_str, cwd) = test_id_to_input(&test_id, &input_paths, &app.app);
-                let generator = test_command_generator(
-                    &group.command,
+                let (input_str, cwd) =
+                    test_id_to_input(test_id, input_paths, &app.app, &group.test_group);
+                let generator = with_log_dir(test_command_generator(
+                    &command,
                     &input_str,
                     cwd,
+                    group.test_group.env_vars(),
                     output_paths.tmp_dir.clone(),
-                );
+                    !run_args.dry_run,
+                ));
                 test_generators.push(TestInstanceCreator {
                     test_id: test_id.clone(),
                     command_generator: generator,
                     is_g_multitest: false,
+                    expected_duration: None,
                 });
             }
             let gtest_generator = gtest_generator.map(|command_generator| TestInstanceCreator {
@@ -105,6 +145,7 @@ pub fn create_run_commands(
                 },
                 command_generator,
                 is_g_multitest: true,
+                expected_duration: None,
             });
             tests.push(TestGroup {
                 app_name: app.name.clone(),
@@ -112,6 +153,9 @@ pub fn create_run_commands(
                 execution_style: execution_style.clone(),
                 timeout,
                 accepted_returncodes: group.test_group.accepted_returncodes.clone(),
+                returncode_outcomes: group.test_group.returncode_outcomes.clone(),
+                resources: group.test_group.resources.clone(),
+                max_parallel: app.app.max_parallel.map(|n| n.get()),
                 tests: test_generators,
             })
         }
@@ -136,9 +180,22 @@ pub struct TestGroup {
     pub execution_style: ExecutionStyle,
     pub timeout: Option<f32>,
     pub accepted_returncodes: Vec<i32>,
+    pub returncode_outcomes: HashMap<i32, config::Outcome>,
+    /// held while a test runs, limited by the capacities given with --resource
+    pub resources: HashMap<String, usize>,
+    /// of the app
+    pub max_parallel: Option<usize>,
     pub tests: Vec<TestInstanceCreator>,
 }
 impl TestGroup {
+    pub fn outcome(&self, exit_code: i32) -> config::Outcome {
+        config::Outcome::from_exit_code(
+            exit_code,
+            &self.accepted_returncodes,
+            &self.returncode_outcomes,
+        )
+    }
+
     pub fn get_timeout_duration(&self) -> Option<std::time::Duration> {
         self.timeout
             .map(|t| std::time::Duration::from_millis((t * 1000.0) as u64))
@@ -149,6 +206,8 @@ pub struct TestInstanceCreator {
     pub test_id: TestId,
     pub command_generator: Box<CommandGenerator>,
     pub is_g_multitest: bool,
+    /// in seconds, tests that take longer are started first
+    pub expected_duration: Option<f32>,
 }
 unsafe impl Sync for TestInstanceCreator {}
 impl TestInstanceCreator {
@@ -164,7 +223,16 @@ impl TestInstanceCreator {
 pub enum ExecutionStyle {
     Single,
     Parallel,
-    XGE,
+    Xge,
+}
+impl std::fmt::Display for ExecutionStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            ExecutionStyle::Single => "singlethreaded",
+            ExecutionStyle::Parallel => "parallel",
+            ExecutionStyle::Xge => "xge",
+        })
+    }
 }
 
 #[derive(Clone)]
@@ -174,10 +242,13 @@ pub struct TestInstance {
 }
 
 impl TestInstance {
-    pub async fn run_async(&self, timeout: Option<std::time::Duration>) -> TestCommandResult {
+    pub async fn run_async(&self, group: &TestGroup) -> TestCommandResult {
+        let timeout = group.get_timeout_duration();
+        let start = std::time::Instant::now();
         let child = Command::new(&self.command.command[0])
             .args(self.command.command[1..].iter())
             .current_dir(&self.command.cwd)
+            .envs(self.command.env.iter().cloned())
             .kill_on_drop(true)
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped())
@@ -187,10 +258,9 @@ impl TestInstance {
             Err(e) => {
                 return TestCommandResult {
                     exit_code: 1,
-                    stdout: format!(
-                        "[mwtest] error while trying to start test: {}",
-                        e.to_string()
-                    ),
+                    outcome: config::Outcome::Fail,
+                    stdout: format!("[mwtest] error while trying to start test: {}", e),
+                    duration: start.elapsed(),
                 };
             }
         };
@@ -244,10 +314,9 @@ impl TestInstance {
             Err(e) => {
                 return TestCommandResult {
                     exit_code: 1,
-                    stdout: format!(
-                        "[mwtest] error while trying to start test: {}",
-                        e.to_string()
-                    ),
+                    outcome: config::Outcome::Fail,
+                    stdout: format!("[mwtest] error while trying to start test: {}", e),
+                    duration: start.elapsed(),
                 }
             }
         };
@@ -261,9 +330,16 @@ impl TestInstance {
                 std::fs::remove_dir(&tmp_path).expect("failed to clean up temporary directory!");
             }
         }
+        // a timeout is a failure, independent of the exit code
+        let outcome = match status.1 {
+            Some(exit_code) => group.outcome(exit_code),
+            None => config::Outcome::Fail,
+        };
         TestCommandResult {
             exit_code,
+            outcome,
             stdout: output_text,
+            duration: start.elapsed(),
         }
     }
 }
@@ -271,24 +347,68 @@ impl TestInstance {
 #[derive(Debug, Clone)]
 pub struct TestCommandResult {
     pub exit_code: i32,
+    pub outcome: config::Outcome,
     pub stdout: String,
+    pub duration: std::time::Duration,
 }
 
 #[derive(Debug, Clone)]
 pub struct TestCommand {
     pub command: Vec<String>,
     pub cwd: String,
+    pub env: Vec<(String, String)>,
     pub tmp_path: Option<PathBuf>,
+    /// folder for the log files of the wrapper ({{wrapper_log_dir}})
+    pub wrapper_log_dir: Option<PathBuf>,
+}
+impl TestCommand {
+    /// The command as one line that can be pasted into a shell (cmd.exe on Windows).
+    pub fn shell_line(&self) -> String {
+        let quote = if cfg!(windows) { quote_cmd } else { quote_posix };
+        let mut parts = vec![];
+        if cfg!(windows) {
+            parts.push(format!("cd /d {} &&", quote(&self.cwd)));
+            for (var, value) in &self.env {
+                parts.push(format!("set {} &&", quote_cmd(&format!("{}={}", var, value))));
+            }
+        } else {
+            parts.push(format!("cd {} &&", quote(&self.cwd)));
+            for (var, value) in &self.env {
+                parts.push(format!("{}={}", var, quote(value)));
+            }
+        }
+        parts.extend(self.command.iter().map(|arg| quote(arg)));
+        parts.join(" ")
+    }
+}
+
+fn quote_posix(arg: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+fn quote_cmd(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "\"&|<>^".contains(c)) {
+        arg.to_string()
+    } else {
+        format!("\"{}\"", arg.replace('"', "\\\""))
+    }
 }
+
 pub type CommandGenerator = dyn Fn() -> TestCommand + Sync + Send;
 
 fn test_id_to_input(
     test_id: &TestId,
     input_paths: &config::InputPaths,
     app: &config::App,
+    test_group: &config::TestGroup,
 ) -> (String, String) {
     if let Some(rel_path) = &test_id.rel_path {
-        let full_path = input_paths.testcases_dir.join(&rel_path);
+        let full_path = input_paths.testcases_dir.join(rel_path);
         let full_path_str = full_path.to_str().unwrap().to_string();
         if let Some(cwd) = &app.build.cwd {
             // cncsim case
@@ -301,6 +421,12 @@ fn test_id_to_input(
             let parent_dir = full_path.parent().unwrap().to_str().unwrap().to_string();
             (full_path_str, parent_dir)
         }
+    } else if test_group.input_strings.is_some() {
+        // input string case
+        (
+            test_id.id.clone(),
+            app.build.cwd.clone().unwrap_or_else(|| ".".to_string()),
+        )
     } else {
         // gtest case
         (
@@ -317,19 +443,25 @@ fn test_command_generator(
     command_template: &config::CommandTemplate,
     input: &str,
     cwd: String,
+    env: Vec<(String, String)>,
     tmp_root: PathBuf,
+    create_tmp_dir: bool,
 ) -> Box<CommandGenerator> {
-    let command = command_template.apply("{{input}}", &input);
+    let command = command_template.apply("{{input}}", input);
     if command.has_pattern("{{generate_output_dir}}") {
         Box::new(move || {
             let tmp_dir = tmp_root.join(PathBuf::from(Uuid::new_v4().to_string()));
             let tmp_path = tmp_dir.to_str().unwrap().to_string();
             let command = command.apply("{{generate_output_dir}}", &tmp_path);
-            std::fs::create_dir(&tmp_path).expect("could not create tmp path!");
+            if create_tmp_dir {
+                std::fs::create_dir(&tmp_path).expect("could not create tmp path!");
+            }
             TestCommand {
                 command: command.0,
                 cwd: cwd.to_string(),
+                env: env.clone(),
                 tmp_path: Some(tmp_dir),
+                wrapper_log_dir: None,
             }
         })
     } else if command.has_pattern("{{generate_output_file}}") {
@@ -340,14 +472,18 @@ fn test_command_generator(
             TestCommand {
                 command: command.0,
                 cwd: cwd.to_string(),
+                env: env.clone(),
                 tmp_path: Some(tmp_dir),
+                wrapper_log_dir: None,
             }
         })
     } else {
         Box::new(move || TestCommand {
             command: command.0.clone(),
             cwd: cwd.to_string(),
+            env: env.clone(),
             tmp_path: None,
+            wrapper_log_dir: None,
         })
     }
 }
@@ -356,11 +492,52 @@ fn gtest_command_generator(
     command_template: &config::CommandTemplate,
     input: &str,
     cwd: String,
+    env: Vec<(String, String)>,
 ) -> Box<CommandGenerator> {
-    let command = command_template.apply("{{input}}", &input);
+    let command = command_template.apply("{{input}}", input);
     Box::new(move || TestCommand {
         command: command.0.clone(),
         cwd: cwd.to_string(),
+        env: env.clone(),
         tmp_path: None,
+        wrapper_log_dir: None,
     })
 }
+
+/// Replaces {{wrapper_log_dir}} by a new folder for each run, whose files become artifacts.
+fn with_wrapper_log_dir(
+    generator: Box<CommandGenerator>,
+    tmp_root: PathBuf,
+    create_tmp_dir: bool,
+) -> Box<CommandGenerator> {
+    Box::new(move || {
+        let mut command = generator();
+        if command.command.iter().any(|t| t.contains("{{wrapper_log_dir}}")) {
+            let log_dir = tmp_root.join(Uuid::new_v4().to_string());
+            for token in &mut command.command {
+                *token = token.replace("{{wrapper_log_dir}}", log_dir.to_str().unwrap());
+            }
+            if create_tmp_dir {
+                std::fs::create_dir(&log_dir).expect("could not create tmp path!");
+            }
+            command.wrapper_log_dir = Some(log_dir);
+        }
+        command
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_args() {
+        assert_eq!(quote_posix("--gtest_filter=A.*"), "'--gtest_filter=A.*'");
+        assert_eq!(quote_posix("/a/b.xml"), "/a/b.xml");
+        assert_eq!(quote_posix("it's"), "'it'\\''s'");
+        assert_eq!(quote_posix(""), "''");
+        assert_eq!(quote_cmd("C:\\a\\b.xml"), "C:\\a\\b.xml");
+        assert_eq!(quote_cmd("a b"), "\"a b\"");
+        assert_eq!(quote_cmd("say \"hi\""), "\"say \\\"hi\\\"\"");
+    }
+}
@@ -1,23 +1,30 @@
 use crate::config;
+use crate::config::Outcome;
+use crate::history::DurationHistory;
 use crate::report;
 use crate::report::Reportable;
 #[cfg(test)]
-use crate::runnable::{ExecutionStyle, TestCommand};
-use crate::runnable::{TestCommandResult, TestGroup, TestInstance, TestInstanceCreator};
+use crate::runnable::TestCommand;
+use crate::runnable::{
+    ExecutionStyle, TestCommandResult, TestGroup, TestInstance, TestInstanceCreator,
+};
 use futures::prelude::*;
 use simple_eyre::eyre::Result;
-use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::{Arc, Mutex};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use tokio::process::Command;
 
 pub fn run(
     input_paths: &config::InputPaths,
-    test_groups: Vec<TestGroup>,
+    mut test_groups: Vec<TestGroup>,
+    skipped: &[(&str, &str, &str)],
     output_paths: &crate::OutputPaths,
     run_args: &crate::RunArgs,
 ) -> Result<bool> {
     let runtime = tokio::runtime::Runtime::new().expect("Unable to create tokio runtime!");
+    let history = DurationHistory::load(&input_paths.build_dir);
+    set_expected_durations(&mut test_groups, &history);
     let mut report = report::Report::new(
         &output_paths.out_dir,
         input_paths
@@ -25,7 +32,11 @@ pub fn run(
             .to_str()
             .expect("Couldn't convert path to string!"),
         run_args.verbose,
+        history,
     )?;
+    for (app_name, test_id, reason) in skipped {
+        report.add_skipped(app_name, test_id, reason);
+    }
 
     if run_args.xge {
         runtime.block_on(async { Ok(run_xge(test_groups, run_args, &mut report, false).await) })
@@ -54,7 +65,7 @@ async fn run_local(
         _ => 1,
     };
 
-    let tests: Vec<(Arc<TestGroup>, TestInstanceCreator)> = test_groups
+    let mut tests: Vec<(Arc<TestGroup>, TestInstanceCreator)> = test_groups
         .into_iter()
         .flat_map(|mut group| match group.gtest_generator.take() {
             Some(gen) => vec![(Arc::new(group), gen)],
@@ -69,56 +80,263 @@ async fn run_local(
         })
         .collect();
 
-    {
-        let instances = tests
-            .iter()
-            .flat_map(|(group, tic)| (0..run_args.repeat).map(move |_| (group, tic)));
-        let mut result_stream = futures::stream::iter(instances)
-            .map(|(group, tic)| {
-                let app_name = group.app_name.clone();
-                let timeout = group.get_timeout_duration();
-                let report = report.clone();
-                async move {
-                    if tic.is_g_multitest {
-                        // no retrying for bundled tests yet
-                        run_gtest(tic.instantiate(), &app_name, report, run_args.fail_fast).await
-                    } else {
-                        for _ in 0..=run_args.repeat_if_failed {
-                            let instance = tic.instantiate();
-                            let result = instance.run_async(timeout).await;
-                            report.lock().unwrap().add(&app_name, instance, &result);
-                            if group.accepted_returncodes.contains(&result.exit_code) {
-                                return true;
-                            }
-                            // test failed, try again
-                        }
-                        // give up retrying: test really failed
-                        false
-                    }
+    sort_longest_first(&mut tests, |(_, tic)| tic);
+    let mut pending: VecDeque<_> = tests
+        .iter()
+        .flat_map(|(group, tic)| (0..run_args.repeat).map(move |_| (group, tic)))
+        .collect();
+    // tests that have to wait for a singlethreaded app or resources don't block the workers, later
+    // tests can start in the meantime
+    let mut usage = Usage::new(run_args.resources.iter().cloned().collect());
+    let mut running = futures::stream::FuturesUnordered::new();
+    let mut overall_success = true;
+    loop {
+        while running.len() < n_workers {
+            let next = pending
+                .iter()
+                .position(|(group, _)| usage.can_start(group));
+            let (group, tic) = match next {
+                Some(i) => pending.remove(i).unwrap(),
+                None => break,
+            };
+            usage.acquire(group);
+            running.push(run_instance(
+                group.clone(),
+                tic,
+                report.clone(),
+                run_args,
+            ));
+        }
+        let (group, success) = match running.next().await {
+            Some(result) => result,
+            None => {
+                // nothing runs that could release what the remaining tests wait for
+                for (group, tic) in pending.drain(..) {
+                    let result = TestCommandResult {
+                        exit_code: -7787,
+                        outcome: Outcome::Fail,
+                        stdout: "[mwtest] the test can never start, the resources or \
+                                 max_parallel of its app can't be satisfied!"
+                            .to_string(),
+                        duration: std::time::Duration::default(),
+                    };
+                    report_by_id(
+                        &*report,
+                        &group.app_name,
+                        tic.test_id.id.clone(),
+                        &result,
+                        None,
+                    );
+                    overall_success = false;
                 }
-            })
-            .buffer_unordered(n_workers);
-
-        let mut overall_success = true;
-        while let Some(success) = result_stream.next().await {
-            overall_success &= success;
-            if !success && run_args.fail_fast {
                 break;
             }
+        };
+        usage.release(&group);
+        overall_success &= success;
+        if !success && run_args.fail_fast {
+            break;
         }
-        overall_success
     }
+    overall_success
+}
+
+/// What the running tests of a local run hold.
+struct Usage {
+    /// capacity per resource, 1 if not listed
+    capacities: HashMap<String, usize>,
+    /// apps with a running test of a singlethreaded group
+    exclusive_apps: HashSet<String>,
+    running_per_app: HashMap<String, usize>,
+    used_resources: HashMap<String, usize>,
+}
+impl Usage {
+    fn new(capacities: HashMap<String, usize>) -> Usage {
+        Usage {
+            capacities,
+            exclusive_apps: HashSet::new(),
+            running_per_app: HashMap::new(),
+            used_resources: HashMap::new(),
+        }
+    }
+
+    fn capacity(&self, resource: &str) -> usize {
+        self.capacities.get(resource).copied().unwrap_or(1)
+    }
+
+    /// A test that needs more than the capacity gets all of it, so that it runs eventually.
+    fn amount(&self, resource: &str, amount: usize) -> usize {
+        amount.min(self.capacity(resource))
+    }
+
+    fn can_start(&self, group: &TestGroup) -> bool {
+        let is_single = matches!(group.execution_style, ExecutionStyle::Single);
+        if is_single && self.exclusive_apps.contains(&group.app_name) {
+            return false;
+        }
+        let running = self.running_per_app.get(&group.app_name).copied();
+        if let (Some(max_parallel), Some(running)) = (group.max_parallel, running) {
+            if running >= max_parallel {
+                return false;
+            }
+        }
+        group.resources.iter().all(|(resource, amount)| {
+            let used = self.used_resources.get(resource).copied().unwrap_or(0);
+            used + self.amount(resource, *amount) <= self.capacity(resource)
+        })
+    }
+
+    fn acquire(&mut self, group: &TestGroup) {
+        if matches!(group.execution_style, ExecutionStyle::Single) {
+            self.exclusive_apps.insert(group.app_name.clone());
+        }
+        *self
+            .running_per_app
+            .entry(group.app_name.clone())
+            .or_default() += 1;
+        for (resource, amount) in &group.resources {
+            let amount = self.amount(resource, *amount);
+            *self.used_resources.entry(resource.clone()).or_default() += amount;
+        }
+    }
+
+    fn release(&mut self, group: &TestGroup) {
+        if matches!(group.execution_style, ExecutionStyle::Single) {
+            self.exclusive_apps.remove(&group.app_name);
+        }
+        *self.running_per_app.get_mut(&group.app_name).unwrap() -= 1;
+        for (resource, amount) in &group.resources {
+            let amount = self.amount(resource, *amount);
+            *self.used_resources.get_mut(resource).unwrap() -= amount;
+        }
+    }
+}
+
+async fn run_instance(
+    group: Arc<TestGroup>,
+    tic: &TestInstanceCreator,
+    report: Arc<Mutex<dyn Reportable>>,
+    run_args: &crate::RunArgs,
+) -> (Arc<TestGroup>, bool) {
+    let success = if tic.is_g_multitest {
+        // no retrying for bundled tests yet
+        run_gtest(tic.instantiate(), &group, report, run_args.fail_fast).await
+    } else {
+        run_with_retries(&group, tic, report, run_args.repeat_if_failed).await
+    };
+    (group, success)
+}
+
+async fn run_with_retries(
+    group: &TestGroup,
+    tic: &TestInstanceCreator,
+    report: Arc<Mutex<dyn Reportable>>,
+    repeat_if_failed: usize,
+) -> bool {
+    for _ in 0..=repeat_if_failed {
+        let instance = tic.instantiate();
+        let result = instance.run_async(group).await;
+        report
+            .lock()
+            .unwrap()
+            .add(&group.app_name, instance, &result);
+        if result.outcome.is_success() {
+            return true;
+        }
+        // test failed, try again
+    }
+    // give up retrying: test really failed
+    false
 }
 
 async fn run_gtest(
     ti: TestInstance,
-    app_name: &str,
+    group: &TestGroup,
     report: Arc<Mutex<dyn Reportable>>,
     fail_fast: bool,
 ) -> bool {
-    let mut child: tokio::process::Child = Command::new(&ti.command.command[0])
-        .args(ti.command.command[1..].iter())
+    let start = tokio::time::Instant::now();
+    // the timeout of the group applies to every test of the batch, the batch as a whole gets
+    // as much time as all of its tests together
+    let test_timeout = group.get_timeout_duration();
+    let batch_timeout = test_timeout.map(|t| t * group.tests.len().max(1) as u32);
+    let timeouts = GtestTimeouts {
+        test: test_timeout,
+        batch: batch_timeout.map(|t| (t, start + t)),
+    };
+
+    let mut command = ti.command.command.clone();
+    let mut completed = vec![];
+    let mut any_failed = false;
+    loop {
+        let end = run_gtest_process(
+            &ti,
+            &command,
+            group,
+            &report,
+            fail_fast,
+            &timeouts,
+            &mut completed,
+        )
+        .await;
+        match end {
+            GtestEnd::Finished { success } => {
+                any_failed |= !success;
+                break;
+            }
+            GtestEnd::Crashed => {
+                any_failed = true;
+                if fail_fast {
+                    break;
+                }
+                // resume with the tests that didn't run yet
+                exclude_from_gtest_filter(&mut command, &completed);
+            }
+            GtestEnd::Aborted => {
+                any_failed = true;
+                break;
+            }
+        }
+    }
+    !any_failed
+}
+
+struct GtestTimeouts {
+    test: Option<std::time::Duration>,
+    /// the duration and the deadline
+    batch: Option<(std::time::Duration, tokio::time::Instant)>,
+}
+
+/// How a gtest process ended.
+enum GtestEnd {
+    /// all tests ran
+    Finished { success: bool },
+    /// a test crashed the process, the tests after it didn't run
+    Crashed,
+    /// by --fail-fast or a timeout
+    Aborted,
+}
+
+/// Runs the batch once and reports the tests as they finish. Their names are added to `completed`,
+/// including a test that crashed. The process is stopped when it runs a test of `completed` again.
+async fn run_gtest_process(
+    ti: &TestInstance,
+    command: &[String],
+    group: &TestGroup,
+    report: &Mutex<dyn Reportable>,
+    fail_fast: bool,
+    timeouts: &GtestTimeouts,
+    completed: &mut Vec<String>,
+) -> GtestEnd {
+    let start = tokio::time::Instant::now();
+    // completed by the processes before this one
+    let n_earlier = completed.len();
+    // the first result of the process takes the logs of the wrapper along
+    let mut wrapper_log_dir = ti.command.wrapper_log_dir.clone();
+    let mut child: tokio::process::Child = Command::new(&command[0])
+        .args(command[1..].iter())
         .current_dir(&ti.command.cwd)
+        .envs(ti.command.env.iter().cloned())
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .kill_on_drop(true)
@@ -143,28 +361,72 @@ async fn run_gtest(
     let mut stderr_reader =
         tokio::io::BufReader::new(child.stderr.take().expect("Failed to open StdErr"));
 
+    let batch_deadline = timeouts.batch.map(|(_, deadline)| deadline);
+    let mut test_deadline = timeouts.test.map(|t| start + t);
+
     let mut current_test = None;
     let mut current_output = String::new();
+    let mut test_start = start;
     let mut any_failed = false;
     loop {
         stdout.line.clear();
         stderr.line.clear();
 
-        let stdout_fut = stdout_reader.read_line(&mut stdout.line).fuse();
-        let stderr_fut = stderr_reader.read_line(&mut stderr.line).fuse();
-        tokio::pin!(stdout_fut);
-        tokio::pin!(stderr_fut);
-
-        let (n_read, pipe) = match (stdout.active, stderr.active) {
-            (true, true) => {
-                tokio::select! {
-                    n_read = stdout_fut => (n_read.unwrap(), &mut stdout),
-                    n_read = stderr_fut => (n_read.unwrap(), &mut stderr),
+        let read = async {
+            let stdout_fut = stdout_reader.read_line(&mut stdout.line).fuse();
+            let stderr_fut = stderr_reader.read_line(&mut stderr.line).fuse();
+            tokio::pin!(stdout_fut);
+            tokio::pin!(stderr_fut);
+            match (stdout.active, stderr.active) {
+                (true, true) => {
+                    tokio::select! {
+                        n_read = stdout_fut => Some((n_read.unwrap(), &mut stdout)),
+                        n_read = stderr_fut => Some((n_read.unwrap(), &mut stderr)),
+                    }
                 }
+                (true, false) => Some((stdout_fut.await.unwrap(), &mut stdout)),
+                (false, true) => Some((stderr_fut.await.unwrap(), &mut stderr)),
+                (false, false) => None,
+            }
+        };
+        let deadline = test_deadline.into_iter().chain(batch_deadline).min();
+        let timeout = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => future::pending().await,
+            }
+        };
+        let (n_read, pipe) = tokio::select! {
+            read = read => match read {
+                Some(read) => read,
+                None => break,
+            },
+            _ = timeout => {
+                let _ = child.kill().await;
+                let reason = match timeouts.batch {
+                    Some((duration, batch_deadline)) if deadline == Some(batch_deadline) => {
+                        format!("the batch timeout of {} seconds", duration.as_secs())
+                    }
+                    _ => format!("the {} second timeout", timeouts.test.unwrap().as_secs()),
+                };
+                current_output += &format!("[mwtest] terminated because {} was reached!", reason);
+                // a hang outside of a test (like in a global set-up) is reported for the batch
+                let id = current_test.take().unwrap_or_else(|| ti.test_id.id.clone());
+                let result = TestCommandResult {
+                    exit_code: -7787,
+                    outcome: Outcome::Fail,
+                    stdout: current_output,
+                    duration: test_start.elapsed(),
+                };
+                report_by_id(
+                    report,
+                    &group.app_name,
+                    id,
+                    &result,
+                    wrapper_log_dir.take(),
+                );
+                return GtestEnd::Aborted;
             }
-            (true, false) => (stdout_fut.await.unwrap(), &mut stdout),
-            (false, true) => (stderr_fut.await.unwrap(), &mut stderr),
-            (false, false) => break,
         };
 
         if n_read == 0 {
@@ -174,46 +436,192 @@ async fn run_gtest(
         let line = &pipe.line;
         // [ RUN      ] RunLocal_OpenGLWrapper.GetVersionTwoContexts
         if line.starts_with("[ RUN      ]") {
-            current_test = Some(line[13..].trim_end().to_string());
+            let test = line[13..].trim_end().to_string();
+            // an executable that ignores the filter would crash again and again
+            if completed[..n_earlier].contains(&test) {
+                let _ = child.kill().await;
+                println!(
+                    "WARNING: {} ran {} again after a crash, it seems to ignore --gtest_filter. \
+                     The rest of the batch is skipped.",
+                    group.app_name, test
+                );
+                return GtestEnd::Aborted;
+            }
+            current_test = Some(test);
             current_output = line.clone();
+            test_start = tokio::time::Instant::now();
+            test_deadline = timeouts.test.map(|t| test_start + t);
         } else {
-            current_output += &line;
-            let mut success = None;
+            current_output += line;
+            let mut outcome = None;
             // [       OK ] RunLocal_OpenGLWrapper.GetVersionTwoContexts (0 ms)
             if line.starts_with("[       OK ]") {
-                success = Some(true);
+                outcome = Some(Outcome::Pass);
+            }
+            // [  SKIPPED ] RunLocal_OpenGLWrapper.GetVersionTwoContexts (0 ms)
+            else if line.starts_with("[  SKIPPED ]") {
+                outcome = Some(Outcome::Skipped);
             }
             // [  FAILED  ] RunLocal_OpenGLWrapper.GetVersionTwoContexts (0 ms)
             else if line.starts_with("[  FAILED  ]") {
-                success = Some(false);
+                outcome = Some(Outcome::Fail);
                 any_failed = true;
                 if fail_fast {
-                    break;
+                    return GtestEnd::Aborted;
                 }
             }
-            if let Some(success) = success {
-                let test_id = crate::TestId {
-                    id: current_test.as_ref().unwrap().clone(),
-                    rel_path: None,
-                };
-                let test_instance = TestInstance {
-                    test_id,
-                    command: crate::runnable::TestCommand {
-                        command: vec![],
-                        cwd: "".into(),
-                        tmp_path: None,
-                    },
-                };
-                let exit_code = if success { 0 } else { 1 };
+            // the summary at the end repeats these lines, but without a preceding [ RUN ]
+            let test = match outcome {
+                Some(_) => current_test.take(),
+                None => None,
+            };
+            if let (Some(outcome), Some(test)) = (outcome, test) {
+                let exit_code = if outcome == Outcome::Fail { 1 } else { 0 };
                 let result = TestCommandResult {
                     exit_code,
+                    outcome,
                     stdout: current_output.clone(),
+                    duration: gtest_duration(line).unwrap_or_default(),
                 };
-                report.lock().unwrap().add(app_name, test_instance, &result);
+                completed.push(test.clone());
+                report_by_id(
+                    report,
+                    &group.app_name,
+                    test,
+                    &result,
+                    wrapper_log_dir.take(),
+                );
             }
         }
     }
-    any_failed
+
+    let exit_code = match child.wait().await {
+        Ok(status) if status.success() => return GtestEnd::Finished { success: !any_failed },
+        Ok(status) => status.code(),
+        Err(_) => None,
+    };
+    let exit_code_text = match exit_code {
+        Some(code) => format!("exit code {}", code),
+        None => "a signal".to_string(),
+    };
+    match current_test {
+        Some(test) => {
+            current_output += &format!("[mwtest] the test crashed with {}!", exit_code_text);
+            let result = TestCommandResult {
+                exit_code: exit_code.unwrap_or(-7787),
+                outcome: Outcome::Fail,
+                stdout: current_output,
+                duration: test_start.elapsed(),
+            };
+            completed.push(test.clone());
+            report_by_id(
+                report,
+                &group.app_name,
+                test,
+                &result,
+                wrapper_log_dir.take(),
+            );
+            GtestEnd::Crashed
+        }
+        // gtest exits with 1 if a test failed, anything else (like a crash in a global set-up)
+        // would otherwise go unnoticed
+        None if !any_failed => {
+            current_output += &format!("[mwtest] the batch ended with {}!", exit_code_text);
+            let result = TestCommandResult {
+                exit_code: exit_code.unwrap_or(-7787),
+                outcome: Outcome::Fail,
+                stdout: current_output,
+                duration: start.elapsed(),
+            };
+            report_by_id(
+                report,
+                &group.app_name,
+                ti.test_id.id.clone(),
+                &result,
+                wrapper_log_dir.take(),
+            );
+            GtestEnd::Aborted
+        }
+        None => GtestEnd::Finished { success: false },
+    }
+}
+
+/// Adds the tests to the negative patterns of the (last) --gtest_filter of the command.
+fn exclude_from_gtest_filter(command: &mut Vec<String>, tests: &[String]) {
+    let excluded = tests.join(":");
+    let prefix = "--gtest_filter=";
+    match command.iter_mut().rev().find(|arg| arg.starts_with(prefix)) {
+        Some(filter) if filter[prefix.len()..].contains('-') => {
+            *filter = format!("{}:{}", filter, excluded)
+        }
+        Some(filter) => *filter = format!("{}-{}", filter, excluded),
+        None => command.push(format!("{}*-{}", prefix, excluded)),
+    }
+}
+
+/// Reports a result without the command that produced it, like a test of a gtest batch.
+fn report_by_id(
+    report: &Mutex<dyn Reportable>,
+    app_name: &str,
+    id: String,
+    result: &TestCommandResult,
+    wrapper_log_dir: Option<std::path::PathBuf>,
+) {
+    let test_instance = TestInstance {
+        test_id: crate::TestId { id, rel_path: None },
+        command: crate::runnable::TestCommand {
+            command: vec![],
+            cwd: "".into(),
+            env: vec![],
+            tmp_path: None,
+            wrapper_log_dir,
+        },
+    };
+    report
+        .lock()
+        .unwrap()
+        .add(app_name, test_instance, result);
+}
+
+/// The duration at the end of lines like "[       OK ] Sample.Test (12 ms)".
+fn gtest_duration(line: &str) -> Option<std::time::Duration> {
+    let ms = line.trim_end().strip_suffix(" ms)")?.rsplit('(').next()?;
+    Some(std::time::Duration::from_millis(ms.parse().ok()?))
+}
+
+fn set_expected_durations(test_groups: &mut [TestGroup], history: &DurationHistory) {
+    for group in test_groups {
+        for tic in &mut group.tests {
+            // the timeout is a rough estimate for tests that were never run
+            tic.expected_duration = history
+                .estimate(&group.app_name, &tic.test_id.id)
+                .or(group.timeout);
+        }
+        // the tests of a gtest batch are recorded one by one, the batch takes as long as all of
+        // them together
+        if let Some(generator) = &mut group.gtest_generator {
+            let estimates: Vec<f32> = group
+                .tests
+                .iter()
+                .filter_map(|tic| tic.expected_duration)
+                .collect();
+            generator.expected_duration = if estimates.is_empty() {
+                None
+            } else {
+                Some(estimates.iter().sum())
+            };
+        }
+    }
+}
+
+/// Longest first, so that a long test that starts last doesn't prolong the run. Tests without an
+/// estimate keep their order at the end.
+fn sort_longest_first<T>(tests: &mut [T], creator: impl Fn(&T) -> &TestInstanceCreator) {
+    tests.sort_by(|a, b| {
+        let a = creator(a).expected_duration.unwrap_or(0.0);
+        let b = creator(b).expected_duration.unwrap_or(0.0);
+        b.total_cmp(&a)
+    });
 }
 
 async fn run_xge(
@@ -245,18 +653,22 @@ async fn run_xge(
     while !done {
         let mut line = Box::pin(reader.next_line().map(|line| {
             let line = line.unwrap().unwrap();
-            if line.starts_with("mwt ") {
-                if line.starts_with("mwt done") {
+            if let Some(message) = line.strip_prefix("mwt ") {
+                if message.starts_with("done") {
                     done = true;
                     return;
                 }
-                let stream_result =
-                    serde_json::from_str::<xge_lib::StreamResult>(&line[4..]).unwrap();
+                let stream_result = serde_json::from_str::<xge_lib::StreamResult>(message).unwrap();
+                let outcome = queue.lock().unwrap().creators[stream_result.id as usize]
+                    .0
+                    .outcome(stream_result.exit_code);
                 let result = TestCommandResult {
                     exit_code: stream_result.exit_code,
+                    outcome,
                     stdout: stream_result.stdout,
+                    duration: std::time::Duration::from_secs_f32(stream_result.duration),
                 };
-                let success = stream_result.exit_code == 0;
+                let success = outcome.is_success();
                 let (group, test_instance, is_done) = {
                     queue.lock().unwrap().return_response(
                         stream_result.id as usize,
@@ -301,7 +713,7 @@ struct TestQueue {
 }
 impl TestQueue {
     fn new(tests: Vec<TestGroup>, repeat: usize) -> TestQueue {
-        let creators: Vec<(Arc<TestGroup>, TestInstanceCreator, Vec<TestInstance>)> = tests
+        let mut creators: Vec<(Arc<TestGroup>, TestInstanceCreator, Vec<TestInstance>)> = tests
             .into_iter()
             .flat_map(|mut group| {
                 let tests: Vec<TestInstanceCreator> = group.tests.drain(0..).collect();
@@ -312,10 +724,10 @@ impl TestQueue {
                     .collect::<Vec<_>>()
             })
             .collect();
+        sort_longest_first(&mut creators, |(_, tic, _)| tic);
         TestQueue {
             indices: (0..creators.len())
-                .map(|i| std::iter::repeat(i).take(repeat))
-                .flatten()
+                .flat_map(|i| std::iter::repeat_n(i, repeat))
                 .collect(),
             creators,
             repeat,
@@ -326,7 +738,7 @@ impl TestQueue {
         match self.indices.pop_front() {
             Some(send_idx) => {
                 let (group, tic, tis) = &mut self.creators[send_idx];
-                assert_eq!(tic.is_g_multitest, false);
+                assert!(!tic.is_g_multitest);
                 let instance = tic.instantiate();
                 tis.push(instance.clone());
                 self.in_flight += 1;
@@ -336,6 +748,7 @@ impl TestQueue {
                     title: instance.test_id.id.clone(),
                     cwd: instance.command.cwd.clone(),
                     command: instance.command.command,
+                    env: instance.command.env,
                     local: matches!(
                         group.execution_style,
                         crate::runnable::ExecutionStyle::Parallel
@@ -373,12 +786,15 @@ impl TestQueue {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     fn make_whoami_instance() -> Vec<TestGroup> {
         let command_generator = Box::new(move || TestCommand {
             command: vec!["whoami".to_owned()],
             cwd: ".".to_owned(),
+            env: vec![],
             tmp_path: None,
+            wrapper_log_dir: None,
         });
         let test = TestInstanceCreator {
             test_id: crate::TestId {
@@ -387,6 +803,7 @@ mod tests {
             },
             command_generator,
             is_g_multitest: false,
+            expected_duration: None,
         };
         vec![TestGroup {
             app_name: "test".to_owned(),
@@ -394,6 +811,9 @@ mod tests {
             execution_style: ExecutionStyle::Parallel,
             timeout: None,
             accepted_returncodes: vec![0],
+            returncode_outcomes: HashMap::new(),
+            resources: HashMap::new(),
+            max_parallel: None,
             tests: vec![test],
         }]
     }
@@ -402,7 +822,9 @@ mod tests {
         let command_generator = Box::new(move || TestCommand {
             command: vec!["ls".to_string(), "/nonexistent-file".to_string()],
             cwd: ".".to_owned(),
+            env: vec![],
             tmp_path: None,
+            wrapper_log_dir: None,
         });
         let test = TestInstanceCreator {
             test_id: crate::TestId {
@@ -411,6 +833,7 @@ mod tests {
             },
             command_generator,
             is_g_multitest: false,
+            expected_duration: None,
         };
         vec![TestGroup {
             app_name: "test".to_owned(),
@@ -418,15 +841,25 @@ mod tests {
             execution_style: ExecutionStyle::Parallel,
             timeout: None,
             accepted_returncodes: vec![0],
+            returncode_outcomes: HashMap::new(),
+            resources: HashMap::new(),
+            max_parallel: None,
             tests: vec![test],
         }]
     }
 
     fn make_echo_instance_for_gtest(output: &'static str) -> Vec<TestGroup> {
+        vec![make_gtest_group(&["/bin/echo", output], None)]
+    }
+
+    fn make_gtest_group(command: &[&str], timeout: Option<f32>) -> TestGroup {
+        let command: Vec<String> = command.iter().map(|arg| arg.to_string()).collect();
         let command_generator = Box::new(move || TestCommand {
-            command: vec!["/bin/echo".into(), output.into()],
+            command: command.clone(),
             cwd: ".".into(),
+            env: vec![],
             tmp_path: None,
+            wrapper_log_dir: None,
         });
         let test = TestInstanceCreator {
             test_id: crate::TestId {
@@ -435,15 +868,19 @@ mod tests {
             },
             command_generator,
             is_g_multitest: true,
+            expected_duration: None,
         };
-        vec![TestGroup {
+        TestGroup {
             app_name: "test".to_owned(),
             gtest_generator: Some(test),
             execution_style: ExecutionStyle::Parallel,
-            timeout: None,
+            timeout,
             accepted_returncodes: vec![0],
+            returncode_outcomes: HashMap::new(),
+            resources: HashMap::new(),
+            max_parallel: None,
             tests: vec![], // TODO
-        }]
+        }
     }
 
     struct CountingReport {
@@ -466,6 +903,54 @@ mod tests {
         }
     }
 
+    struct RunConfig {
+        verbose: bool,
+        parallel: bool,
+        xge: bool,
+        repeat: RepeatStrategy,
+    }
+    enum RepeatStrategy {
+        Repeat(usize),
+        RepeatIfFailed(usize),
+    }
+    impl RunConfig {
+        fn to_run_args(&self) -> crate::RunArgs {
+            let (repeat, repeat_if_failed) = match self.repeat {
+                RepeatStrategy::Repeat(n) => (n, 0),
+                RepeatStrategy::RepeatIfFailed(n) => (1, n),
+            };
+            crate::RunArgs {
+                app_names: vec![],
+                id: vec![],
+                filter: vec![],
+                filter_regex: vec![],
+                exclude_filter: vec![],
+                refresh_discovery: false,
+                shard: None,
+                verbose: self.verbose,
+                dry_run: false,
+                // fixed worker count, so that tests don't depend on the CPU count of the machine
+                parallel: if self.parallel { Some(Some(4)) } else { None },
+                xge: self.xge,
+                no_monitor: true,
+                repeat,
+                repeat_if_failed,
+                treat_completion_as_success: false,
+                fail_fast: false,
+                no_timeout: false,
+                timeout: None,
+                timeout_factor: 1.0,
+                exclusion_file: None,
+                run_only_changed_file: None,
+                extra_args: vec![],
+                no_wrapper: false,
+                wrapper: None,
+                wrapper_timeout_factor: None,
+                resources: vec![],
+            }
+        }
+    }
+
     fn count_results(tests: Vec<TestGroup>, run_config: RunConfig) -> (bool, usize) {
         let runtime = tokio::runtime::Runtime::new().expect("Unable to create tokio runtime!");
         let report = Arc::new(Mutex::new(CountingReport::new()));
@@ -473,9 +958,9 @@ mod tests {
             /*let ctrl_c = tokio::signal::ctrl_c();
             select! {
                 _ = ctrl_c => false,
-                success = run_local(tests, &run_config, report.clone()) => success,
+                success = run_local(tests, &run_config.to_run_args(), report.clone()) => success,
             }*/
-            run_local(tests, &run_config, report.clone()).await
+            run_local(tests, &run_config.to_run_args(), report.clone()).await
         });
         let count = report.lock().unwrap().count;
         (success, count)
@@ -484,17 +969,23 @@ mod tests {
     fn count_results_xge(tests: Vec<TestGroup>, run_config: RunConfig) -> (bool, usize) {
         let runtime = tokio::runtime::Runtime::new().expect("Unable to create tokio runtime!");
         let mut report = CountingReport::new();
-        let success =
-            runtime.block_on(async { run_xge(tests, &run_config, &mut report, true).await });
+        let success = runtime
+            .block_on(async { run_xge(tests, &run_config.to_run_args(), &mut report, true).await });
         (success, report.count)
     }
 
     struct CollectingReport {
         ids: Vec<String>,
+        outputs: Vec<String>,
+        wrapper_log_dirs: Vec<Option<std::path::PathBuf>>,
     }
     impl CollectingReport {
         fn new() -> Self {
-            Self { ids: vec![] }
+            Self {
+                ids: vec![],
+                outputs: vec![],
+                wrapper_log_dirs: vec![],
+            }
         }
     }
     impl Reportable for CollectingReport {
@@ -503,27 +994,32 @@ mod tests {
             &mut self,
             _app_name: &str,
             test_instance: crate::runnable::TestInstance,
-            _test_result: &crate::scheduler::TestCommandResult,
+            test_result: &crate::scheduler::TestCommandResult,
         ) {
             self.ids.push(test_instance.test_id.id);
+            self.outputs.push(test_result.stdout.clone());
+            self.wrapper_log_dirs
+                .push(test_instance.command.wrapper_log_dir);
         }
     }
 
     fn collect_results(tests: Vec<TestGroup>, run_config: RunConfig) -> (bool, Vec<String>) {
         let runtime = tokio::runtime::Runtime::new().expect("Unable to create tokio runtime!");
         let report = Arc::new(Mutex::new(CollectingReport::new()));
-        let success =
-            runtime.block_on(async { run_local(tests, &run_config, report.clone()).await });
+        let success = runtime
+            .block_on(async { run_local(tests, &run_config.to_run_args(), report.clone()).await });
         let ids = report.lock().unwrap().ids.clone();
         (success, ids)
     }
 
-    fn collect_results_gtest(test: TestInstance) -> (bool, Vec<String>) {
+    fn collect_results_gtest(mut group: TestGroup) -> (bool, Vec<String>, Vec<String>) {
         let runtime = tokio::runtime::Runtime::new().expect("Unable to create tokio runtime!");
         let report = Arc::new(Mutex::new(CollectingReport::new()));
-        let success = runtime.block_on(async { run_gtest(test, "app_name", report.clone()).await });
-        let ids = report.lock().unwrap().ids.clone();
-        (success, ids)
+        let test = group.gtest_generator.take().unwrap().instantiate();
+        let success =
+            runtime.block_on(async { run_gtest(test, &group, report.clone(), false).await });
+        let report = report.lock().unwrap();
+        (success, report.ids.clone(), report.outputs.clone())
     }
 
     #[test]
@@ -537,7 +1033,7 @@ mod tests {
                 repeat: RepeatStrategy::Repeat(1),
             },
         );
-        assert_eq!(success, true);
+        assert!(success);
         assert_eq!(count, 1);
     }
 
@@ -552,12 +1048,148 @@ Some prefix
 [  FAILED  ] Sample.Failed
 "#,
         );
-        let (success, ids) =
-            collect_results_gtest(tests[0].gtest_generator.take().unwrap().instantiate());
-        assert_eq!(success, false);
+        let (success, ids, _) = collect_results_gtest(tests.remove(0));
+        assert!(!success);
         assert_eq!(ids, ["Sample.Succeed", "Sample.Failed"]);
     }
 
+    #[test]
+    fn test_run_gtest_timeout() {
+        let script = r#"
+echo "[ RUN      ] Sample.Succeed"
+echo "[       OK ] Sample.Succeed (0 ms)"
+echo "[ RUN      ] Sample.Hang"
+echo "partial output"
+sleep 10
+"#;
+        let mut group = make_gtest_group(&["sh", "-c", script], Some(1.0));
+        group.tests = make_whoami_instance().remove(0).tests;
+        group.tests.extend(make_whoami_instance().remove(0).tests);
+        let start = std::time::Instant::now();
+        let (success, ids, outputs) = collect_results_gtest(group);
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert!(!success);
+        assert_eq!(ids, ["Sample.Succeed", "Sample.Hang"]);
+        assert!(outputs[1].contains("partial output"));
+        assert!(outputs[1].contains("terminated because the 1 second timeout was reached"));
+    }
+
+    #[test]
+    fn test_run_gtest_resumes_after_crash() {
+        // the second launch excludes the tests of the first one
+        let script = r#"
+case "$1" in
+*-Sample.First:Sample.Crash)
+  echo "[ RUN      ] Sample.Last"
+  echo "$1"
+  echo "[       OK ] Sample.Last (0 ms)"
+  ;;
+*)
+  echo "[ RUN      ] Sample.First"
+  echo "[       OK ] Sample.First (0 ms)"
+  echo "[ RUN      ] Sample.Crash"
+  echo "about to crash"
+  kill -SEGV $$
+  ;;
+esac
+"#;
+        let group = make_gtest_group(&["sh", "-c", script, "sh", "--gtest_filter=Sample.*"], None);
+        let (success, ids, outputs) = collect_results_gtest(group);
+        assert!(!success);
+        assert_eq!(ids, ["Sample.First", "Sample.Crash", "Sample.Last"]);
+        assert!(outputs[1].contains("about to crash"));
+        assert!(outputs[1].contains("[mwtest] the test crashed with a signal!"));
+        assert!(outputs[2].contains("--gtest_filter=Sample.*-Sample.First:Sample.Crash"));
+
+        // without a filter, it would crash again, but every test is only reported once
+        let script = r#"
+echo "[ RUN      ] Sample.First"
+echo "[       OK ] Sample.First (0 ms)"
+echo "[ RUN      ] Sample.Crash"
+kill -SEGV $$
+"#;
+        let group = make_gtest_group(&["sh", "-c", script], None);
+        let (success, ids, _) = collect_results_gtest(group);
+        assert!(!success);
+        assert_eq!(ids, ["Sample.First", "Sample.Crash"]);
+    }
+
+    #[test]
+    fn test_run_gtest_wrapper_logs() {
+        let script = r#"
+case "$1" in
+*-Sample.First:Sample.Crash)
+  echo "[ RUN      ] Sample.Last"
+  echo "[       OK ] Sample.Last (0 ms)"
+  ;;
+*)
+  echo "[ RUN      ] Sample.First"
+  echo "[       OK ] Sample.First (0 ms)"
+  echo "[ RUN      ] Sample.Crash"
+  kill -SEGV $$
+  ;;
+esac
+"#;
+        let mut group =
+            make_gtest_group(&["sh", "-c", script, "sh", "--gtest_filter=Sample.*"], None);
+        let mut test = group.gtest_generator.take().unwrap().instantiate();
+        let log_dir = std::path::PathBuf::from("/wrapper_logs");
+        test.command.wrapper_log_dir = Some(log_dir.clone());
+        let runtime = tokio::runtime::Runtime::new().expect("Unable to create tokio runtime!");
+        let report = Arc::new(Mutex::new(CollectingReport::new()));
+        runtime.block_on(async { run_gtest(test, &group, report.clone(), false).await });
+        // the logs of each process go with its first test
+        let report = report.lock().unwrap();
+        assert_eq!(report.ids, ["Sample.First", "Sample.Crash", "Sample.Last"]);
+        assert_eq!(
+            report.wrapper_log_dirs,
+            [Some(log_dir.clone()), None, Some(log_dir)]
+        );
+    }
+
+    #[test]
+    fn test_run_gtest_exit_code_outside_of_tests() {
+        let script = "echo 'global set-up failed'; exit 3";
+        let group = make_gtest_group(&["sh", "-c", script], None);
+        let (success, ids, outputs) = collect_results_gtest(group);
+        assert!(!success);
+        assert_eq!(ids, ["test_id"]);
+        assert!(outputs[0].contains("global set-up failed"));
+        assert!(outputs[0].contains("exit code 3"));
+    }
+
+    #[test]
+    fn test_exclude_from_gtest_filter() {
+        let done = ["A.x".to_string(), "B.y".to_string()];
+        let mut command = vec!["exe".to_string(), "--gtest_filter=A.*:B.*".to_string()];
+        exclude_from_gtest_filter(&mut command, &done);
+        assert_eq!(command[1], "--gtest_filter=A.*:B.*-A.x:B.y");
+        exclude_from_gtest_filter(&mut command, &["C.z".to_string()]);
+        assert_eq!(command[1], "--gtest_filter=A.*:B.*-A.x:B.y:C.z");
+
+        let mut command = vec!["exe".to_string()];
+        exclude_from_gtest_filter(&mut command, &done);
+        assert_eq!(command, ["exe", "--gtest_filter=*-A.x:B.y"]);
+    }
+
+    #[test]
+    fn test_run_gtest_batch_timeout() {
+        // every test is faster than the timeout, but the batch (of one test) is not
+        let script = r#"
+echo "[ RUN      ] Sample.First"
+sleep 0.3
+echo "[       OK ] Sample.First (300 ms)"
+echo "[ RUN      ] Sample.Second"
+sleep 0.3
+echo "[       OK ] Sample.Second (300 ms)"
+"#;
+        let group = make_gtest_group(&["sh", "-c", script], Some(0.5));
+        let (success, ids, outputs) = collect_results_gtest(group);
+        assert!(!success);
+        assert_eq!(ids, ["Sample.First", "Sample.Second"]);
+        assert!(outputs[1].contains("batch timeout"));
+    }
+
     #[test]
     fn test_run_normal_and_gtest() {
         let mut tests = make_echo_instance_for_gtest(
@@ -577,7 +1209,10 @@ Some prefix
                 repeat: RepeatStrategy::Repeat(2),
             },
         );
-        assert_eq!(success, false);
+        // parallel runs finish in arbitrary order
+        let mut ids = ids;
+        ids.sort();
+        assert!(success);
         assert_eq!(
             ids,
             ["Sample.Succeed", "Sample.Succeed", "test_id", "test_id"]
@@ -595,7 +1230,7 @@ Some prefix
                 repeat: RepeatStrategy::Repeat(1),
             },
         );
-        assert_eq!(success, true);
+        assert!(success);
         assert_eq!(count, 1);
     }
 
@@ -610,7 +1245,7 @@ Some prefix
                 repeat: RepeatStrategy::Repeat(10),
             },
         );
-        assert_eq!(success, true);
+        assert!(success);
         assert_eq!(count, 10);
     }
 
@@ -625,7 +1260,7 @@ Some prefix
                 repeat: RepeatStrategy::Repeat(10),
             },
         );
-        assert_eq!(success, true);
+        assert!(success);
         assert_eq!(count, 10);
     }
 
@@ -640,7 +1275,7 @@ Some prefix
                 repeat: RepeatStrategy::RepeatIfFailed(5),
             },
         );
-        assert_eq!(success, false);
+        assert!(!success);
         assert_eq!(count, 6);
     }
 
@@ -655,15 +1290,35 @@ Some prefix
                 repeat: RepeatStrategy::RepeatIfFailed(5),
             },
         );
-        assert_eq!(success, false);
+        assert!(!success);
         assert_eq!(count, 6);
     }
 
+    #[test]
+    fn test_run_returncode_outcomes() {
+        let mut tests = make_failing_ls_instance();
+        tests[0].returncode_outcomes.insert(2, Outcome::KnownIssue);
+        let (success, count) = count_results(
+            tests,
+            RunConfig {
+                verbose: false,
+                parallel: false,
+                xge: false,
+                repeat: RepeatStrategy::RepeatIfFailed(5),
+            },
+        );
+        // known issues are neither failures nor retried
+        assert!(success);
+        assert_eq!(count, 1);
+    }
+
     fn make_sleep_instance(timeout: Option<f32>) -> TestGroup {
         let command_generator = Box::new(move || TestCommand {
             command: vec!["sleep".to_owned(), "1".to_owned()],
             cwd: ".".to_owned(),
+            env: vec![],
             tmp_path: None,
+            wrapper_log_dir: None,
         });
         TestGroup {
             app_name: "test".to_owned(),
@@ -671,6 +1326,9 @@ Some prefix
             execution_style: ExecutionStyle::Parallel,
             timeout,
             accepted_returncodes: vec![0],
+            returncode_outcomes: HashMap::new(),
+            resources: HashMap::new(),
+            max_parallel: None,
             tests: vec![TestInstanceCreator {
                 test_id: crate::TestId {
                     id: format!("{:?}", timeout),
@@ -678,6 +1336,7 @@ Some prefix
                 },
                 command_generator,
                 is_g_multitest: false,
+                expected_duration: None,
             }],
         }
     }
@@ -693,7 +1352,7 @@ Some prefix
                 repeat: RepeatStrategy::Repeat(10),
             },
         );
-        assert_eq!(success, false);
+        assert!(!success);
         assert_eq!(count, 10);
     }
 
@@ -716,4 +1375,198 @@ Some prefix
         // tests should finish in the order of their expected duration
         assert_eq!(ids, vec!["Some(0.001)", "Some(0.05)", "Some(0.1)"]);
     }
+
+    /// Tests that fail if another one holds the lock folder at the same time.
+    fn make_lock_dir_group(app_name: &str, style: ExecutionStyle, lock_name: &str) -> TestGroup {
+        let lock_dir = std::env::temp_dir().join(format!(
+            "mwtest_{}_{}",
+            lock_name,
+            std::process::id()
+        ));
+        let script = format!(
+            "mkdir '{0}' || exit 1; sleep 0.1; rmdir '{0}'",
+            lock_dir.display()
+        );
+        let tests = (0..3)
+            .map(|i| {
+                let script = script.clone();
+                TestInstanceCreator {
+                    test_id: crate::TestId {
+                        id: format!("test{}", i),
+                        rel_path: None,
+                    },
+                    command_generator: Box::new(move || TestCommand {
+                        command: vec!["sh".into(), "-c".into(), script.clone()],
+                        cwd: ".".into(),
+                        env: vec![],
+                        tmp_path: None,
+                        wrapper_log_dir: None,
+                    }),
+                    is_g_multitest: false,
+                    expected_duration: None,
+                }
+            })
+            .collect();
+        TestGroup {
+            app_name: app_name.to_owned(),
+            gtest_generator: None,
+            execution_style: style,
+            timeout: None,
+            accepted_returncodes: vec![0],
+            returncode_outcomes: HashMap::new(),
+            resources: HashMap::new(),
+            max_parallel: None,
+            tests,
+        }
+    }
+
+    fn run_parallel(tests: Vec<TestGroup>) -> (bool, Vec<String>) {
+        collect_results(
+            tests,
+            RunConfig {
+                verbose: false,
+                parallel: true,
+                xge: false,
+                repeat: RepeatStrategy::Repeat(1),
+            },
+        )
+    }
+
+    #[test]
+    fn test_run_local_singlethreaded_is_exclusive() {
+        let single = make_lock_dir_group("single", ExecutionStyle::Single, "single");
+        let (success, ids) = run_parallel(vec![single, make_sleep_instance(None)]);
+        assert!(success);
+        assert_eq!(ids.len(), 4);
+    }
+
+    #[test]
+    fn test_run_local_max_parallel() {
+        let mut group = make_lock_dir_group("limited", ExecutionStyle::Parallel, "limited");
+        group.max_parallel = Some(1);
+        let (success, ids) = run_parallel(vec![group]);
+        assert!(success);
+        assert_eq!(ids.len(), 3);
+    }
+
+    #[test]
+    fn test_run_local_stuck() {
+        // max_parallel 0 is rejected when apps.json is loaded, here it lets only one test start
+        let mut group = make_lock_dir_group("stuck", ExecutionStyle::Parallel, "stuck");
+        group.max_parallel = Some(0);
+        let runtime = tokio::runtime::Runtime::new().expect("Unable to create tokio runtime!");
+        let report = Arc::new(Mutex::new(CollectingReport::new()));
+        let run_args = RunConfig {
+            verbose: false,
+            parallel: true,
+            xge: false,
+            repeat: RepeatStrategy::Repeat(1),
+        }
+        .to_run_args();
+        let success =
+            runtime.block_on(async { run_local(vec![group], &run_args, report.clone()).await });
+        assert!(!success);
+        let report = report.lock().unwrap();
+        assert_eq!(report.ids, ["test0", "test1", "test2"]);
+        assert!(!report.outputs[0].contains("can never start"));
+        assert!(report.outputs[1].contains("the test can never start"));
+        assert!(report.outputs[2].contains("the test can never start"));
+    }
+
+    #[test]
+    fn test_run_local_resources_are_shared_between_apps() {
+        let groups: Vec<_> = ["a", "b"]
+            .iter()
+            .map(|app_name| {
+                let mut group = make_lock_dir_group(app_name, ExecutionStyle::Parallel, "license");
+                // more than the default capacity of 1
+                group.resources.insert("license".into(), 2);
+                group
+            })
+            .collect();
+        let (success, ids) = run_parallel(groups);
+        assert!(success);
+        assert_eq!(ids.len(), 6);
+    }
+
+    fn make_timed_groups() -> Vec<TestGroup> {
+        [
+            ("short", Some(1.0)),
+            ("unknown", None),
+            ("long", Some(300.0)),
+        ]
+        .iter()
+        .map(|(id, expected_duration)| {
+            let mut group = make_whoami_instance().remove(0);
+            group.tests[0].test_id.id = id.to_string();
+            group.tests[0].expected_duration = *expected_duration;
+            group
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_run_local_longest_first() {
+        let (success, ids) = collect_results(
+            make_timed_groups(),
+            RunConfig {
+                verbose: false,
+                parallel: false,
+                xge: false,
+                repeat: RepeatStrategy::Repeat(1),
+            },
+        );
+        assert!(success);
+        assert_eq!(ids, vec!["long", "short", "unknown"]);
+    }
+
+    #[test]
+    fn test_xge_queue_longest_first() {
+        let mut queue = TestQueue::new(make_timed_groups(), 1);
+        let titles: Vec<_> = std::iter::from_fn(|| queue.next_request())
+            .map(|request| request.title)
+            .collect();
+        assert_eq!(titles, ["long", "short", "unknown"]);
+    }
+
+    #[test]
+    fn test_expected_durations() {
+        let build_dir =
+            std::env::temp_dir().join(format!("mwtest_expected_{}", std::process::id()));
+        let mut history = DurationHistory::load(&build_dir);
+        history.record("test", "Sample.First", std::time::Duration::from_secs(2));
+        history.record("test", "Sample.Second", std::time::Duration::from_secs(3));
+
+        let mut batch = make_gtest_group(&["true"], None);
+        batch.tests = ["Sample.First", "Sample.Second", "Sample.New"]
+            .iter()
+            .map(|id| {
+                let mut tic = make_whoami_instance().remove(0).tests.remove(0);
+                tic.test_id.id = id.to_string();
+                tic
+            })
+            .collect();
+        let mut unknown = make_gtest_group(&["true"], None);
+        unknown.tests = make_whoami_instance().remove(0).tests;
+        let mut groups = vec![batch, unknown, make_sleep_instance(Some(4.0))];
+        set_expected_durations(&mut groups, &history);
+
+        let batch = &groups[0];
+        assert_eq!(batch.tests[0].expected_duration, Some(2.0));
+        assert_eq!(batch.tests[2].expected_duration, None);
+        let batch_estimate = |group: &TestGroup| group.gtest_generator.as_ref()?.expected_duration;
+        assert_eq!(batch_estimate(batch), Some(5.0));
+        assert_eq!(batch_estimate(&groups[1]), None);
+        // the timeout when there is no history
+        assert_eq!(groups[2].tests[0].expected_duration, Some(4.0));
+    }
+
+    #[test]
+    fn test_gtest_duration() {
+        assert_eq!(
+            gtest_duration("[       OK ] Sample.Test (12 ms)\n"),
+            Some(std::time::Duration::from_millis(12))
+        );
+        assert_eq!(gtest_duration("[  FAILED  ] Sample.Test"), None);
+    }
 }
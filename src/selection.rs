@@ -0,0 +1,294 @@
+use crate::config::AppConfig;
+use simple_eyre::eyre::{eyre, Result};
+
+/// An expression that selects apps, like `tag:machsimtests & !app:machsimPerformance`.
+///
+/// Terms are matched case-insensitively:
+/// - `name` matches an app name, alias or tag (or every app for `all`)
+/// - `app:name` matches an app name or alias
+/// - `tag:name` matches a tag
+/// - `responsible:"3Axis Team"` matches the responsible team
+///
+/// Terms can be combined with `!`, `&`, `|` and parentheses. Terms separated by whitespace only
+/// are combined with `|`, so "a b" selects both apps.
+#[derive(Debug, PartialEq)]
+pub enum AppSelector {
+    Any(String),
+    App(String),
+    Tag(String),
+    Responsible(String),
+    Not(Box<AppSelector>),
+    And(Box<AppSelector>, Box<AppSelector>),
+    Or(Box<AppSelector>, Box<AppSelector>),
+}
+
+impl AppSelector {
+    /// Parses the app names given on the command line. An app is selected if it matches any of
+    /// them.
+    pub fn parse_all(expressions: &[String]) -> Result<Option<AppSelector>> {
+        let mut selector = None;
+        for expression in expressions {
+            let parsed = AppSelector::parse(expression)?;
+            selector = Some(match selector {
+                Some(s) => AppSelector::Or(Box::new(s), Box::new(parsed)),
+                None => parsed,
+            });
+        }
+        Ok(selector)
+    }
+
+    pub fn parse(expression: &str) -> Result<AppSelector> {
+        let tokens = tokenize(expression)?;
+        if tokens.is_empty() {
+            return Err(eyre!("empty app selection"));
+        }
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+        };
+        let selector = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(selector),
+            Some(token) => Err(eyre!(
+                "unexpected '{}' in app selection '{}'",
+                token,
+                expression
+            )),
+        }
+    }
+
+    pub fn matches(&self, name: &str, app: &AppConfig) -> bool {
+        let eq = |a: &str, b: &str| a.to_lowercase() == b.to_lowercase();
+        let is_app = |n: &str| eq(n, name) || app.alias.iter().any(|a| eq(a, n));
+        let is_tag = |n: &str| app.tags.iter().any(|t| eq(t, n));
+        match self {
+            AppSelector::Any(n) => eq(n, "all") || is_app(n) || is_tag(n),
+            AppSelector::App(n) => is_app(n),
+            AppSelector::Tag(n) => is_tag(n),
+            AppSelector::Responsible(n) => eq(n, &app.responsible),
+            AppSelector::Not(s) => !s.matches(name, app),
+            AppSelector::And(a, b) => a.matches(name, app) && b.matches(name, app),
+            AppSelector::Or(a, b) => a.matches(name, app) || b.matches(name, app),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Term(String),
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Term(t) => write!(f, "{}", t),
+            Token::Not => write!(f, "!"),
+            Token::And => write!(f, "&"),
+            Token::Or => write!(f, "|"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '!' => Token::Not,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            _ => {
+                let mut term = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if c == '"' {
+                        quoted = !quoted;
+                    } else if !quoted && (c.is_whitespace() || "!&|()".contains(c)) {
+                        break;
+                    } else {
+                        term.push(c);
+                    }
+                    chars.next();
+                }
+                if quoted {
+                    return Err(eyre!(
+                        "unterminated quote in app selection '{}'",
+                        expression
+                    ));
+                }
+                tokens.push(Token::Term(term));
+                continue;
+            }
+        };
+        chars.next();
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    // or := and (('|')? and)*
+    fn parse_or(&mut self) -> Result<AppSelector> {
+        let mut selector = self.parse_and()?;
+        loop {
+            match self.peek() {
+                Some(Token::Or) => {
+                    self.next();
+                }
+                Some(Token::Term(_)) | Some(Token::Not) | Some(Token::Open) => {}
+                _ => return Ok(selector),
+            }
+            let rhs = self.parse_and()?;
+            selector = AppSelector::Or(Box::new(selector), Box::new(rhs));
+        }
+    }
+
+    // and := unary ('&' unary)*
+    fn parse_and(&mut self) -> Result<AppSelector> {
+        let mut selector = self.parse_unary()?;
+        while let Some(Token::And) = self.peek() {
+            self.next();
+            let rhs = self.parse_unary()?;
+            selector = AppSelector::And(Box::new(selector), Box::new(rhs));
+        }
+        Ok(selector)
+    }
+
+    // unary := '!' unary | '(' or ')' | term
+    fn parse_unary(&mut self) -> Result<AppSelector> {
+        match self.next() {
+            Some(Token::Not) => Ok(AppSelector::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open) => {
+                let selector = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(selector),
+                    _ => Err(eyre!("missing ')' in app selection")),
+                }
+            }
+            Some(Token::Term(term)) => parse_term(term),
+            Some(token) => Err(eyre!("unexpected '{}' in app selection", token)),
+            None => Err(eyre!("incomplete app selection")),
+        }
+    }
+}
+
+fn parse_term(term: &str) -> Result<AppSelector> {
+    let (key, value) = match term.find(':') {
+        Some(i) => (&term[..i], &term[i + 1..]),
+        None => return Ok(AppSelector::Any(term.to_string())),
+    };
+    if value.is_empty() {
+        return Err(eyre!("missing value after '{}:' in app selection", key));
+    }
+    let value = value.to_string();
+    match key.to_lowercase().as_str() {
+        "app" => Ok(AppSelector::App(value)),
+        "tag" => Ok(AppSelector::Tag(value)),
+        "responsible" => Ok(AppSelector::Responsible(value)),
+        _ => Err(eyre!(
+            "unknown key '{}' in app selection (expected app, tag or responsible)",
+            key
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(alias: &[&str], tags: &[&str], responsible: &str) -> AppConfig {
+        let json = format!(
+            r#"{{
+                "command": ["{{{{exe}}}}"],
+                "responsible": "{}",
+                "alias": {:?},
+                "tags": {:?},
+                "builds": {{}},
+                "tests": {{}}
+            }}"#,
+            responsible, alias, tags
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    fn selected(expression: &str) -> Vec<&'static str> {
+        let apps = [
+            (
+                "machsim",
+                app(&["ms"], &["machsimtests"], "Simulation Team"),
+            ),
+            (
+                "machsimPerformance",
+                app(&[], &["machsimtests"], "Simulation Team"),
+            ),
+            ("cadcam", app(&[], &[], "3Axis Team")),
+        ];
+        let selector = AppSelector::parse(expression).unwrap();
+        apps.iter()
+            .filter(|(name, config)| selector.matches(name, config))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    #[test]
+    fn plain_names() {
+        assert_eq!(selected("MS"), ["machsim"]);
+        assert_eq!(selected("machsimtests"), ["machsim", "machsimPerformance"]);
+        assert_eq!(selected("all").len(), 3);
+        assert_eq!(selected("ms cadcam"), ["machsim", "cadcam"]);
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            selected("tag:machsimtests & !app:machsimPerformance"),
+            ["machsim"]
+        );
+        assert_eq!(selected(r#"responsible:"3axis team""#), ["cadcam"]);
+        assert_eq!(selected("app:ms | app:cadcam"), ["machsim", "cadcam"]);
+        assert_eq!(
+            selected("!(tag:machsimtests & app:ms)"),
+            ["machsimPerformance", "cadcam"]
+        );
+        assert!(selected("tag:ms").is_empty());
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        for expression in &["", "a &", "(a", "a)", "owner:x", "tag:", "responsible:\"x"] {
+            assert!(
+                AppSelector::parse(expression).is_err(),
+                "'{}' should not parse",
+                expression
+            );
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use simple_eyre::eyre::{eyre, Report, Result};
+use std::str::FromStr;
+
+/// One of `count` disjoint parts of the selected tests, given as "INDEX/COUNT" (like "2/4").
+///
+/// Tests are assigned by a hash of app name and test id, so that adding or removing a test doesn't
+/// move other tests to another shard.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shard {
+    /// 1-based
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Shard {
+    pub fn contains(&self, app_name: &str, test_id: &str) -> bool {
+        stable_hash(app_name, test_id) % self.count == self.index - 1
+    }
+}
+
+impl FromStr for Shard {
+    type Err = Report;
+    fn from_str(s: &str) -> Result<Shard> {
+        let invalid = || eyre!("invalid shard '{}' (expected INDEX/COUNT, like 1/4)", s);
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index: u64 = index.trim().parse().map_err(|_| invalid())?;
+        let count: u64 = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+/// FNV-1a, which (unlike std's DefaultHasher) is the same for every Rust version and platform.
+fn stable_hash(app_name: &str, test_id: &str) -> u64 {
+    let normalized_id = test_id.replace('\\', "/");
+    let bytes = app_name
+        .to_lowercase()
+        .into_bytes()
+        .into_iter()
+        .chain(std::iter::once(0))
+        .chain(normalized_id.into_bytes());
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "2/4".parse::<Shard>().unwrap(),
+            Shard { index: 2, count: 4 }
+        );
+        for invalid in &["0/4", "5/4", "1/0", "1", "a/b", "1/4/2"] {
+            assert!(invalid.parse::<Shard>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn shards_are_disjoint_and_stable() {
+        let ids: Vec<String> = (0..100).map(|i| format!("dir/test{}", i)).collect();
+        let shards: Vec<Shard> = (1..=3).map(|i| Shard { index: i, count: 3 }).collect();
+        for id in &ids {
+            let n = shards.iter().filter(|s| s.contains("app", id)).count();
+            assert_eq!(n, 1, "{}", id);
+        }
+        for shard in &shards {
+            let n = ids.iter().filter(|id| shard.contains("app", id)).count();
+            assert!(n > 20, "shard {:?} only has {} tests", shard, n);
+        }
+        assert_eq!(
+            stable_hash("app", "dir\\test0"),
+            stable_hash("APP", "dir/test0")
+        );
+        // the assignment must not change between versions
+        assert_eq!(stable_hash("a", "b"), 0xe5d2_9919_0426_66b2);
+    }
+}
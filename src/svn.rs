@@ -14,14 +14,14 @@ pub fn update(
 
     check_svn_available()?;
 
-    let (branch_url, dev_revision) = get_dev_branch_and_revision(&dev_dir, verbose)?;
+    let (branch_url, dev_revision) = get_dev_branch_and_revision(dev_dir, verbose)?;
     let next_dev_revision = get_next_revision(&branch_url, dev_revision, verbose)?;
     let testcase_relative_paths: Vec<_> =
         testcase_relative_paths.iter().map(PathBuf::from).collect();
     update_revision(
         &branch_url,
         next_dev_revision,
-        &testcase_root_dir,
+        testcase_root_dir,
         &testcase_relative_paths,
         verbose,
     )
@@ -43,16 +43,16 @@ fn update_revision(
         println!("Selected revision: {}", next_dev_revision);
     }
 
-    let testcases_revision = detect_testcases_revision(&branch_url, next_dev_revision)?;
+    let testcases_revision = detect_testcases_revision(branch_url, next_dev_revision)?;
 
     let mut wcs = vec![];
     for test_dir in itertools::sorted(testcase_relative_paths) {
-        wcs.append(&mut svn_find_workingcopies(&testcase_root_dir, &test_dir)?);
+        wcs.append(&mut svn_find_workingcopies(testcase_root_dir, test_dir)?);
     }
     switch_workingcopies(
         &wcs,
-        &testcase_root_dir,
-        &branch_url,
+        testcase_root_dir,
+        branch_url,
         testcases_revision,
         verbose,
     )?;
@@ -71,13 +71,13 @@ pub fn checkout(
 
     check_svn_available()?;
 
-    let (branch_url, dev_revision) = get_dev_branch_and_revision(&dev_dir, verbose)?;
+    let (branch_url, dev_revision) = get_dev_branch_and_revision(dev_dir, verbose)?;
     let next_dev_revision = get_next_revision(&branch_url, dev_revision, verbose)?;
     checkout_revision(
         &branch_url,
         next_dev_revision,
-        &testcase_root_dir,
-        &testcase_relative_paths,
+        testcase_root_dir,
+        testcase_relative_paths,
         force_conversion,
         minimal,
         verbose,
@@ -102,9 +102,9 @@ pub fn checkout_revision(
         println!("Selected revision: {}", next_dev_revision);
     }
 
-    let testcases_revision = detect_testcases_revision(&branch_url, next_dev_revision)?;
+    let testcases_revision = detect_testcases_revision(branch_url, next_dev_revision)?;
 
-    let mut depth = svn_depth(&testcase_root_dir, Path::new(""));
+    let mut depth = svn_depth(testcase_root_dir, Path::new(""));
 
     let testcase_relative_paths: Vec<PathBuf> =
         testcase_relative_paths.iter().map(PathBuf::from).collect();
@@ -112,28 +112,28 @@ pub fn checkout_revision(
     match &depth {
         None => {
             create_checkout_and_convert(
-                &testcase_root_dir,
-                &branch_url,
+                testcase_root_dir,
+                branch_url,
                 testcases_revision,
                 force_conversion,
                 verbose,
             )
             .wrap_err("Failed to create checkout")?;
 
-            depth = svn_depth(&testcase_root_dir, Path::new(""));
+            depth = svn_depth(testcase_root_dir, Path::new(""));
             if minimal {
-                remove_unneeded_testcases(&testcase_root_dir, &testcase_relative_paths, verbose)?;
+                remove_unneeded_testcases(testcase_root_dir, &testcase_relative_paths, verbose)?;
             }
         }
         Some(_) => {
             if minimal {
                 // remove before switch to avoid unneded large switches
-                remove_unneeded_testcases(&testcase_root_dir, &testcase_relative_paths, verbose)?;
+                remove_unneeded_testcases(testcase_root_dir, &testcase_relative_paths, verbose)?;
             }
             switch_workingcopies(
                 &[testcase_root_dir.to_owned()],
-                &testcase_root_dir,
-                &branch_url,
+                testcase_root_dir,
+                branch_url,
                 testcases_revision,
                 verbose,
             )?;
@@ -142,7 +142,7 @@ pub fn checkout_revision(
 
     if depth.as_deref() == Some("empty") {
         create_missing_testcases(
-            &testcase_root_dir,
+            testcase_root_dir,
             &testcase_relative_paths,
             testcases_revision,
             verbose,
@@ -186,7 +186,7 @@ fn create_checkout_and_convert(
 ) -> Result<()> {
     assert!(PathBuf::from(testcase_root_dir).is_absolute());
 
-    let nested_checkouts = svn_find_workingcopies(&testcase_root_dir, Path::new(""))?;
+    let nested_checkouts = svn_find_workingcopies(testcase_root_dir, Path::new(""))?;
     if !nested_checkouts.is_empty() {
         if !force_conversion {
             return Err(eyre!(concat!(
@@ -205,15 +205,15 @@ fn create_checkout_and_convert(
         // when .svn index is deleted
         switch_workingcopies(
             &nested_checkouts,
-            &testcase_root_dir,
-            &branch_url,
+            testcase_root_dir,
+            branch_url,
             revision,
             verbose,
         )?;
     }
 
     for wc in &nested_checkouts {
-        let status = status(&wc)?;
+        let status = status(wc)?;
         let has_not_allowed_status = status.target.entry.iter().any(|t| {
             ["conflicted", "unversioned", "added", "deleted", "replaced"]
                 .contains(&t.wc_status.item.as_str())
@@ -254,7 +254,7 @@ fn create_checkout_and_convert(
             }
             delete_svn_index(&wc_path);
             // TODO: do nested checkouts always have depth=infinity?
-            svn_make_sparse(&testcase_root_dir, &PathBuf::from(wc_relpath), revision)?;
+            svn_make_sparse(testcase_root_dir, &PathBuf::from(wc_relpath), revision)?;
         }
     }
     Ok(())
@@ -271,7 +271,7 @@ fn remove_unneeded_testcases(
         path: &Path,
         testcase_root_dir: &Path,
         testcase_relative_paths: &[PathBuf],
-        mut unneeded_paths: &mut Vec<String>,
+        unneeded_paths: &mut Vec<String>,
     ) {
         let abs_path = testcase_root_dir.join(path);
         if !abs_path.exists() || path.file_name().map(|n| n == ".svn").unwrap_or(false) {
@@ -290,18 +290,18 @@ fn remove_unneeded_testcases(
             for f in std::fs::read_dir(abs_path).unwrap() {
                 recursive_find_unneeded(
                     &path.join(f.unwrap().path().file_name().unwrap()),
-                    &testcase_root_dir,
-                    &testcase_relative_paths,
-                    &mut unneeded_paths,
+                    testcase_root_dir,
+                    testcase_relative_paths,
+                    unneeded_paths,
                 );
             }
         }
     }
 
     recursive_find_unneeded(
-        &Path::new(""),
-        &testcase_root_dir,
-        &testcase_relative_paths,
+        Path::new(""),
+        testcase_root_dir,
+        testcase_relative_paths,
         &mut unneeded_paths,
     );
 
@@ -320,10 +320,7 @@ fn remove_unneeded_testcases(
                     path
                 );
             } else {
-                svn(
-                    &["update", "--set-depth=exclude", &path],
-                    &testcase_root_dir,
-                )?;
+                svn(&["update", "--set-depth=exclude", &path], testcase_root_dir)?;
             }
         }
     }
@@ -338,7 +335,7 @@ fn create_missing_testcases(
 ) -> Result<()> {
     let mut missing_paths = vec![];
     for test_path in testcase_relative_paths {
-        if svn_depth(&test_path, testcase_root_dir).as_deref() != Some("infinity") {
+        if svn_depth(test_path, testcase_root_dir).as_deref() != Some("infinity") {
             missing_paths.push(test_path);
         }
     }
@@ -349,9 +346,9 @@ fn create_missing_testcases(
         }
         for test_path in itertools::sorted(missing_paths) {
             if verbose {
-                print_svn_path_p(&test_path);
+                print_svn_path_p(test_path);
             }
-            svn_make_sparse(&testcase_root_dir, &test_path, revision)?;
+            svn_make_sparse(testcase_root_dir, test_path, revision)?;
         }
     }
     Ok(())
@@ -379,7 +376,7 @@ fn switch_workingcopies(
         wcs.sort();
         wcs.dedup();
         for wc in wcs {
-            let wc_relpath = svn_relpath_p(&wc, &testcases_root_path);
+            let wc_relpath = svn_relpath_p(&wc, testcases_root_path);
             if verbose && wc_relpath != "." && !wc_relpath.is_empty() {
                 print_svn_path(&wc_relpath);
             }
@@ -406,7 +403,7 @@ fn svn_make_sparse(root: &Path, path: &Path, revision: Revision) -> Result<()> {
     for i in 0..path_list.len() {
         let sub_path = itertools::join(&path_list[0..=i], "/");
         let sub_path = Path::new(&sub_path);
-        let current_depth = svn_depth(&sub_path, root);
+        let current_depth = svn_depth(sub_path, root);
         let needed_depth = if i + 1 < path_list.len() {
             "empty"
         } else {
@@ -434,9 +431,9 @@ fn svn_make_sparse(root: &Path, path: &Path, revision: Revision) -> Result<()> {
                     "--accept=postpone",
                     "--revision",
                     &revision.to_string(),
-                    &sub_path.to_str().unwrap(),
+                    sub_path.to_str().unwrap(),
                 ],
-                &root,
+                root,
             )?;
 
             // svn update silently does nothing if an url does not exist.
@@ -575,7 +572,7 @@ pub fn get_dev_branch_and_revision(dev_dir: &Path, verbose: bool) -> Result<(Str
         println!("Checking {:?}", dev_dir);
     }
 
-    let dev_info = info(&dev_dir)?;
+    let dev_info = info(dev_dir)?;
     let dev_info = dev_info.entry.unwrap();
     if !dev_info.relative_url.ends_with("/dev") {
         return Err(eyre!(
@@ -634,7 +631,7 @@ fn get_next_revision(branch_url: &str, dev_revision: u32, verbose: bool) -> Resu
 }
 
 fn svn_depth(local_path: &Path, _cwd: &Path) -> Option<String> {
-    Some(info(&local_path).ok()?.entry?.wc_info.depth)
+    Some(info(local_path).ok()?.entry?.wc_info.depth)
 }
 
 fn svn_revision(local_path: &Path) -> Result<u32> {
@@ -653,7 +650,7 @@ fn svn_revisions(path: &Path) -> Result<Vec<u32>> {
     };
     let output = Command::new("svnversion")
         .arg(file)
-        .current_dir(&dir)
+        .current_dir(dir)
         .output()
         .unwrap();
     let output = std::str::from_utf8(&output.stdout).unwrap();
@@ -753,6 +750,7 @@ struct StatusTarget {
 #[derive(Deserialize, Debug)]
 #[serde(rename = "entry")]
 struct StatusEntry {
+    #[allow(dead_code)]
     path: String,
     #[serde(rename = "wc-status")]
     wc_status: WcStatus,
@@ -810,7 +808,7 @@ fn info(path: &Path) -> Result<Info> {
         (path, ".")
     };
     let output = Command::new("svn")
-        .args(&["info", "--xml", &file])
+        .args(["info", "--xml", file])
         .current_dir(dir)
         .output()?;
     if !output.status.success() {
@@ -824,7 +822,7 @@ fn info(path: &Path) -> Result<Info> {
         return Err(eyre!("SVN log failed: {:?}", output));
     }
     let output = std::str::from_utf8(&output.stdout).unwrap();
-    Ok(serde_xml_rs::from_str(&output)?)
+    Ok(serde_xml_rs::from_str(output)?)
 }
 
 #[derive(Deserialize, Debug)]
@@ -846,16 +844,16 @@ fn log(
     limit: Option<u32>,
 ) -> Result<Log> {
     let mut cmd = Command::new("svn");
-    cmd.args(&[
+    cmd.args([
         "log",
         "--xml",
         "-v",
         "-r",
         &format!("{}:{}", revision_start, revision_end),
-        &root,
+        root,
     ]);
     if let Some(limit) = limit {
-        cmd.args(&["-l", &format!("{}", limit)]);
+        cmd.args(["-l", &format!("{}", limit)]);
     }
     let output = cmd.output()?;
     if !output.status.success() {
@@ -920,7 +918,7 @@ mod tests {
 
     fn checkout_empty(path: &str) {
         assert!(Command::new("svn")
-            .args(&[
+            .args([
                 "checkout",
                 "--depth=empty",
                 path,
@@ -933,7 +931,7 @@ mod tests {
 
     fn checkout(path: &str) {
         assert!(Command::new("svn")
-            .args(&["checkout", path, Path::new(ROOT).to_str().unwrap()])
+            .args(["checkout", path, Path::new(ROOT).to_str().unwrap()])
             .status()
             .unwrap()
             .success());
@@ -952,7 +950,7 @@ mod tests {
         setup();
         checkout(DEV_URL);
         assert_eq!(
-            &super::info(&Path::new(ROOT)).unwrap().entry.unwrap().url,
+            &super::info(Path::new(ROOT)).unwrap().entry.unwrap().url,
             DEV_URL
         );
     }
@@ -1071,8 +1069,8 @@ mod tests {
         setup();
         for f in &TEST_FOLDERS {
             let path = &Path::new(ROOT).join(f);
-            std::fs::create_dir_all(&path).unwrap();
-            super::svn(&["checkout", &(TEST_URL.to_owned() + "/" + f), "."], &path).unwrap();
+            std::fs::create_dir_all(path).unwrap();
+            super::svn(&["checkout", &(TEST_URL.to_owned() + "/" + f), "."], path).unwrap();
         }
 
         assert_eq!(
@@ -1093,10 +1091,10 @@ mod tests {
 
         {
             let wd = &Path::new(ROOT).join("someOtherDirName/");
-            std::fs::create_dir_all(&wd).unwrap();
+            std::fs::create_dir_all(wd).unwrap();
             super::svn(
                 &["checkout", &(TEST_URL.to_owned() + "/" + TEST_FOLDERS[0])],
-                &wd,
+                wd,
             )
             .unwrap();
         }
@@ -1140,8 +1138,14 @@ mod tests {
         checkout(TEST_URL);
         let initial_revision = super::svn_revision(Path::new(ROOT)).unwrap();
         let new_revision = super::Revision::Revision(initial_revision - 1);
-        super::switch_workingcopies(&[root.clone()], &root, BRANCH_URL, new_revision, false)
-            .unwrap();
+        super::switch_workingcopies(
+            std::slice::from_ref(&root),
+            &root,
+            BRANCH_URL,
+            new_revision,
+            false,
+        )
+        .unwrap();
         assert_eq!(
             super::Revision::Revision(super::svn_revision(Path::new(ROOT)).unwrap()),
             new_revision
@@ -0,0 +1,43 @@
+use simple_eyre::eyre::{eyre, Result};
+
+/// Splits at whitespace, except inside double quotes.
+pub fn split_quoted(line: &str) -> Result<Vec<String>> {
+    let mut tokens = vec![];
+    let mut token: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        if c == '"' {
+            quoted = !quoted;
+            token.get_or_insert_with(String::new);
+        } else if c.is_whitespace() && !quoted {
+            tokens.extend(token.take());
+        } else {
+            token.get_or_insert_with(String::new).push(c);
+        }
+    }
+    if quoted {
+        return Err(eyre!("unterminated quote"));
+    }
+    tokens.extend(token);
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_at_unquoted_whitespace() {
+        assert_eq!(
+            split_quoted(r#"valgrind  --log-file="{{wrapper_log_dir}}/a b.log" "" --"#).unwrap(),
+            vec![
+                "valgrind",
+                "--log-file={{wrapper_log_dir}}/a b.log",
+                "",
+                "--"
+            ]
+        );
+        assert!(split_quoted("").unwrap().is_empty());
+        assert!(split_quoted(r#"app --id "a"#).is_err());
+    }
+}
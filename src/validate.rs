@@ -0,0 +1,375 @@
+use crate::config::{AppConfig, AppsConfig, BuildConfig, CommandTemplate, TestGroupConfig};
+use simple_eyre::eyre::Result;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Placeholders that are replaced for every command, independent of the build.
+const COMMAND_PLACEHOLDERS: &[&str] = &[
+    "input",
+    "generate_output_dir",
+    "generate_output_file",
+    "dev_dir",
+    "build_dir",
+    "testcases_dir",
+    "build_config",
+    "build_config_dir",
+    "build_config_skipunicode",
+    "extra_args",
+    "wrapper_log_dir",
+];
+// Placeholders that can be used in the "builds" section and in environment variables.
+const BUILD_PLACEHOLDERS: &[&str] = &[
+    "dev_dir",
+    "build_dir",
+    "testcases_dir",
+    "build_config",
+    "build_config_dir",
+    "build_config_skipunicode",
+];
+
+pub struct Problem {
+    pub location: String,
+    pub message: String,
+}
+
+/// Validates apps.json and all fragments in apps.d, including names that are used in more than
+/// one file.
+pub fn validate_dir(dir: &Path) -> Result<Vec<(PathBuf, Problem)>> {
+    let mut problems = vec![];
+    let mut fragments = vec![];
+    for file in AppsConfig::files(dir) {
+        for problem in validate_file(&file)? {
+            problems.push((file.clone(), problem));
+        }
+        if let Ok(fragment) = AppsConfig::load_file(&file) {
+            fragments.push((fragment, file));
+        }
+    }
+    for (name, first, second) in AppsConfig::find_duplicates(&fragments) {
+        let problem = Problem {
+            location: name,
+            message: format!("also defined in {}", first.display()),
+        };
+        problems.push((second, problem));
+    }
+    Ok(problems)
+}
+
+pub fn validate_file(path: &Path) -> Result<Vec<Problem>> {
+    validate(&std::fs::read_to_string(path)?)
+}
+
+fn validate(content: &str) -> Result<Vec<Problem>> {
+    let deserializer = &mut serde_json::Deserializer::from_str(content);
+    if let Err(e) = serde_path_to_error::deserialize::<_, AppsConfig>(deserializer) {
+        return Ok(vec![Problem {
+            location: e.path().to_string(),
+            message: e.inner().to_string(),
+        }]);
+    }
+
+    let mut problems = vec![];
+    let deserializer = &mut serde_json::Deserializer::from_str(content);
+    let apps: AppsConfig = serde_ignored::deserialize(deserializer, |key| {
+        problems.push(Problem {
+            location: format_ignored_path(&key),
+            message: "unknown key".to_string(),
+        })
+    })?;
+
+    for name in apps.app_names() {
+        validate_app(&name, &apps.0[&name], &mut problems);
+    }
+    Ok(problems)
+}
+
+fn validate_app(name: &str, app: &AppConfig, problems: &mut Vec<Problem>) {
+    let mut builds: Vec<_> = app.builds.iter().collect();
+    builds.sort_by_key(|(build_type, _)| *build_type);
+    for (build_type, build) in &builds {
+        let location = format!("{}.builds.{}", name, build_type);
+        if build.exe.is_none() {
+            problems.push(Problem {
+                location: location.clone(),
+                message: "no exe defined".to_string(),
+            });
+        }
+        let build_strings = [
+            ("exe", &build.exe),
+            ("dll", &build.dll),
+            ("cwd", &build.cwd),
+            ("solution", &build.solution),
+        ];
+        for (key, value) in build_strings.iter() {
+            if let Some(value) = value {
+                validate_build_string(value, key, &location, problems);
+            }
+        }
+    }
+
+    validate_env(&app.env, name, problems);
+    if let Some(env_path) = &app.env_path {
+        validate_build_string(env_path, "env_path", name, problems);
+    }
+
+    let mut preset_names: Vec<_> = app.tests.keys().collect();
+    preset_names.sort();
+    for preset_name in preset_names {
+        let preset = &app.tests[preset_name];
+        let location = format!("{}.tests.{}", name, preset_name);
+        if let Some(id_pattern) = &preset.id_pattern {
+            match regex::Regex::new(id_pattern) {
+                Ok(re) if re.captures_len() < 2 => problems.push(Problem {
+                    location: location.clone(),
+                    message: format!("id_pattern '{}' has no capture group", id_pattern),
+                }),
+                Ok(_) => {}
+                Err(e) => problems.push(Problem {
+                    location: location.clone(),
+                    message: format!("id_pattern '{}' is invalid: {}", id_pattern, e),
+                }),
+            }
+        }
+        if let Err(e) = app.resolve_preset(preset_name) {
+            problems.push(Problem {
+                location: location.clone(),
+                message: e.to_string(),
+            });
+        }
+        validate_env(&preset.env, &location, problems);
+        let preset_command = preset.command.as_ref().unwrap_or(&app.command);
+        for (i, group) in preset.groups.iter().enumerate() {
+            let location = format!("{}.groups[{}]", location, i);
+            validate_group(group, &location, problems);
+            validate_env(&group.env, &location, problems);
+            let command = group.command.as_ref().unwrap_or(preset_command);
+            validate_command(command, &builds, &location, problems);
+            if let Some(wrapper) = &group.wrapper {
+                validate_command(wrapper, &builds, &format!("{}.wrapper", location), problems);
+            }
+        }
+    }
+}
+
+fn validate_group(group: &TestGroupConfig, location: &str, problems: &mut Vec<Problem>) {
+    let n_generators = [
+        group.find_glob.is_some(),
+        group.find_parent_dir.is_some(),
+        group.find_gtest.is_some(),
+        group.input_strings.is_some(),
+    ]
+    .iter()
+    .filter(|g| **g)
+    .count();
+    if n_generators == 0 {
+        problems.push(Problem {
+            location: location.to_string(),
+            message: "no test generator defined (find_glob, find_parent_dir, find_gtest or \
+                      input_strings)"
+                .to_string(),
+        });
+    } else if n_generators > 1 {
+        problems.push(Problem {
+            location: location.to_string(),
+            message: "more than one test generator defined".to_string(),
+        });
+    }
+    if !["singlethreaded", "parallel", "xge"].contains(&group.execution_style.as_str()) {
+        problems.push(Problem {
+            location: location.to_string(),
+            message: format!(
+                "invalid execution_style '{}' (expected 'singlethreaded', 'parallel' or 'xge')",
+                group.execution_style
+            ),
+        });
+    }
+}
+
+fn validate_env(env: &HashMap<String, String>, location: &str, problems: &mut Vec<Problem>) {
+    let mut vars: Vec<_> = env.iter().collect();
+    vars.sort();
+    for (var, value) in vars {
+        validate_build_string(value, var, location, problems);
+    }
+}
+
+fn validate_build_string(value: &str, key: &str, location: &str, problems: &mut Vec<Problem>) {
+    for placeholder in placeholders(value) {
+        if !BUILD_PLACEHOLDERS.contains(&placeholder) {
+            problems.push(Problem {
+                location: location.to_string(),
+                message: format!("unknown placeholder {{{{{}}}}} in {}", placeholder, key),
+            });
+        }
+    }
+}
+
+fn validate_command(
+    command: &CommandTemplate,
+    builds: &[(&String, &BuildConfig)],
+    location: &str,
+    problems: &mut Vec<Problem>,
+) {
+    let mut used: Vec<&str> = command.0.iter().flat_map(|t| placeholders(t)).collect();
+    used.sort_unstable();
+    used.dedup();
+    for placeholder in used {
+        match placeholder {
+            // a missing exe is already reported for the build itself
+            "exe" => {}
+            "dll" => {
+                for (build_type, build) in builds.iter().filter(|(_, b)| !b.disabled) {
+                    if build.dll.is_none() {
+                        problems.push(Problem {
+                            location: location.to_string(),
+                            message: format!(
+                                "command uses {{{{dll}}}}, but build '{}' has no dll",
+                                build_type
+                            ),
+                        });
+                    }
+                }
+            }
+            p if COMMAND_PLACEHOLDERS.contains(&p) => {}
+            p => problems.push(Problem {
+                location: location.to_string(),
+                message: format!("unknown placeholder {{{{{}}}}}", p),
+            }),
+        }
+    }
+}
+
+fn placeholders(s: &str) -> Vec<&str> {
+    s.split("{{")
+        .skip(1)
+        .filter_map(|t| t.split("}}").next())
+        .collect()
+}
+
+fn format_ignored_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => format!("{}[{}]", format_ignored_path(parent), index),
+        Path::Map { parent, key } => match format_ignored_path(parent) {
+            p if p.is_empty() => key.to_string(),
+            p => format!("{}.{}", p, key),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => format_ignored_path(parent),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(apps_json: &str) -> Vec<(String, String)> {
+        validate(apps_json)
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.location, p.message))
+            .collect()
+    }
+
+    #[test]
+    fn valid_config() {
+        let apps_json = r#"{
+            "app": {
+                "command": ["{{exe}}", "{{extra_args}}", "{{input}}"],
+                "responsible": "someone",
+                "builds": { "quickstart": { "exe": "{{build_dir}}/app.exe" } },
+                "tests": {
+                    "ci": {
+                        "id_pattern": "app/(.*)",
+                        "groups": [ { "find_glob": "app/**/*.xml" } ]
+                    }
+                }
+            }
+        }"#;
+        assert!(problems(apps_json).is_empty());
+    }
+
+    #[test]
+    fn type_error_has_location() {
+        let apps_json = r#"{
+            "app": {
+                "command": ["{{exe}}"],
+                "responsible": "someone",
+                "builds": {},
+                "tests": { "ci": { "groups": [ { "find_glob": "*", "timeout": "long" } ] } }
+            }
+        }"#;
+        let problems = problems(apps_json);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].0, "app.tests.ci.groups[0].timeout");
+    }
+
+    #[test]
+    fn max_parallel_is_positive() {
+        let apps_json = r#"{
+            "app": {
+                "command": ["{{exe}}"],
+                "responsible": "someone",
+                "builds": {},
+                "tests": {},
+                "max_parallel": 0
+            }
+        }"#;
+        let problems = problems(apps_json);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].0, "app.max_parallel");
+    }
+
+    #[test]
+    fn reports_all_problems() {
+        let apps_json = r#"{
+            "app": {
+                "command": ["{{exe}}", "{{dll}}", "{{unknown}}"],
+                "responsible": "someone",
+                "builds": { "quickstart": { "exe": "app.exe" } },
+                "tests": {
+                    "ci": {
+                        "id_pattern": "app/.*",
+                        "groups": [
+                            { "find_glob": "*", "find_parnet_dir": "*" },
+                            { "command": ["{{exe}}"], "execution_style": "sometimes" }
+                        ]
+                    },
+                    "nightly": { "extends": ["ci", "weekly"], "groups": [] }
+                }
+            }
+        }"#;
+        let problems = problems(apps_json);
+        let expected = [
+            ("app.tests.ci.groups[0].find_parnet_dir", "unknown key"),
+            ("app.tests.ci", "id_pattern 'app/.*' has no capture group"),
+            (
+                "app.tests.ci.groups[0]",
+                "command uses {{dll}}, but build 'quickstart' has no dll",
+            ),
+            ("app.tests.ci.groups[0]", "unknown placeholder {{unknown}}"),
+            (
+                "app.tests.ci.groups[1]",
+                "no test generator defined (find_glob, find_parent_dir, find_gtest or \
+                 input_strings)",
+            ),
+            ("app.tests.nightly", "preset 'weekly' is not defined"),
+            (
+                "app.tests.ci.groups[1]",
+                "invalid execution_style 'sometimes' (expected 'singlethreaded', 'parallel' or \
+                 'xge')",
+            ),
+        ];
+        for (location, message) in expected.iter() {
+            assert!(
+                problems.contains(&(location.to_string(), message.to_string())),
+                "missing problem: {}: {}",
+                location,
+                message
+            );
+        }
+        assert_eq!(problems.len(), expected.len(), "{:?}", problems);
+    }
+}